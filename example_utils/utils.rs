@@ -1,9 +1,13 @@
 use bevy::{input::system::exit_on_esc_system, prelude::*};
 use bevy_prototype_character_controller::{
+    camera::{CameraBoom, CameraMode, HideInFirstPerson, ViewMode},
     controller::{
-        BodyTag, CameraTag, CharacterController, CharacterControllerPlugin, HeadTag, Mass, YawTag,
+        BodyTag, CameraTag, CharacterController, CharacterControllerPlugin, HeadEntity, HeadTag,
+        Mass, YawTag,
     },
     events::{ControllerEvents, TranslationEvent},
+    head_bob::HeadBob,
+    lean::Lean,
     look::{LookDirection, LookEntity},
 };
 use rand::Rng;
@@ -104,6 +108,7 @@ pub fn spawn_character(
             CharacterController::default(),
             FakeKinematicRigidBody,
             Mass::new(80.0),
+            HeadBob::default(),
             BodyTag,
         ))
         .current_entity()
@@ -123,6 +128,7 @@ pub fn spawn_character(
             )),
             ..Default::default()
         })
+        .with(HideInFirstPerson)
         .current_entity()
         .expect("Failed to spawn body_model");
     let head = commands
@@ -134,6 +140,7 @@ pub fn spawn_character(
                 (0.5 * character_settings.scale.y + character_settings.head_scale) * Vec3::unit_y(),
             )),
             HeadTag,
+            Lean::default(),
         ))
         .current_entity()
         .expect("Failed to spawn head");
@@ -144,8 +151,27 @@ pub fn spawn_character(
             transform: Transform::from_scale(Vec3::splat(character_settings.head_scale)),
             ..Default::default()
         })
+        .with(HideInFirstPerson)
         .current_entity()
         .expect("Failed to spawn head_model");
+    // A follow offset of zero means the character starts in first person
+    let first_person = CameraBoom::looking_at(Vec3::zero(), -Vec3::unit_z());
+    let camera_mode = if character_settings.follow_offset == Vec3::zero() {
+        CameraMode {
+            mode: ViewMode::FirstPerson,
+            blend: 0.0,
+            ..Default::default()
+        }
+    } else {
+        CameraMode::new(
+            ViewMode::ThirdPerson,
+            first_person,
+            CameraBoom::looking_at(
+                character_settings.follow_offset,
+                character_settings.focal_point,
+            ),
+        )
+    };
     let camera = commands
         .spawn(Camera3dBundle {
             transform: Transform::from_matrix(Mat4::face_toward(
@@ -155,11 +181,11 @@ pub fn spawn_character(
             )),
             ..Default::default()
         })
-        .with_bundle((LookDirection::default(), CameraTag))
+        .with_bundle((LookDirection::default(), CameraTag, camera_mode))
         .current_entity()
         .expect("Failed to spawn camera");
     commands
-        .insert_one(body, LookEntity(camera))
+        .insert(body, (LookEntity(camera), HeadEntity(head)))
         .push_children(body, &[yaw])
         .push_children(yaw, &[body_model, head])
         .push_children(head, &[head_model, camera]);
@@ -1,11 +1,14 @@
 use bevy::{input::system::exit_on_esc_system, prelude::*};
 use bevy_prototype_character_controller::{
     controller::{
-        BodyTag, CameraTag, CharacterController, CharacterControllerPlugin, HeadTag, Mass, YawTag,
+        BodyTag, CameraTag, CharacterController, CharacterControllerPlugin, HeadEntity, HeadTag,
+        Mass, YawTag,
     },
     events::{ControllerEvents, TranslationEvent},
+    lean::Lean,
     look::{LookDirection, LookEntity},
     physx::*,
+    probe::Ground,
 };
 use bevy_prototype_physx::*;
 use clap::{arg_enum, value_t};
@@ -173,6 +176,11 @@ pub fn spawn_character(
             dynamic_friction: 0.5,
             restitution: 0.0,
         },
+        Ground::new(
+            0.5 * character_settings.scale.y
+                + 0.5 * character_settings.scale.x.max(character_settings.scale.z)
+                + 0.1,
+        ),
         BodyTag,
     ));
 
@@ -272,6 +280,7 @@ fn spawn_body_children(
                 head_translation,
             )),
             HeadTag,
+            Lean::default(),
         ))
         .current_entity()
         .expect("Failed to spawn head");
@@ -297,7 +306,7 @@ fn spawn_body_children(
         .current_entity()
         .expect("Failed to spawn camera");
     commands
-        .insert_one(body, LookEntity(camera))
+        .insert(body, (LookEntity(camera), HeadEntity(head)))
         .push_children(body, &[yaw])
         .push_children(yaw, &[body_model, head])
         .push_children(head, &[head_model, camera]);
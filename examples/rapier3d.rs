@@ -1,8 +1,11 @@
 use bevy::{input::system::exit_on_esc_system, prelude::*};
 use bevy_prototype_character_controller::{
-    controller::{BodyTag, CameraTag, CharacterController, HeadTag, YawTag},
+    controller::{BodyTag, CameraTag, CharacterController, HeadEntity, HeadTag, YawTag},
+    lean::Lean,
     look::{LookDirection, LookEntity},
+    probe::Ground,
     rapier::*,
+    wall_run::WallRun,
 };
 use bevy_rapier3d::{
     physics::{PhysicsInterpolationComponent, RapierConfiguration, RapierPhysicsPlugin},
@@ -158,6 +161,13 @@ pub fn spawn_character(
                 0.5 * (box_y + character_settings.scale.y) * Vec3::unit_y(),
                 Quat::identity(),
             ),
+            // Half the capsule height plus its radius, with some skin
+            Ground::new(
+                0.5 * character_settings.scale.y
+                    + 0.5 * character_settings.scale.x.max(character_settings.scale.z)
+                    + 0.1,
+            ),
+            WallRun::default(),
             BodyTag,
         ))
         .current_entity()
@@ -198,6 +208,7 @@ pub fn spawn_character(
                 ),
             )),
             HeadTag,
+            Lean::default(),
         ))
         .current_entity()
         .expect("Failed to spawn head");
@@ -223,7 +234,7 @@ pub fn spawn_character(
         .current_entity()
         .expect("Failed to spawn camera");
     commands
-        .insert_one(body, LookEntity(camera))
+        .insert(body, (LookEntity(camera), HeadEntity(head)))
         .push_children(body, &[yaw])
         .push_children(yaw, &[body_model, head])
         .push_children(head, &[head_model, camera]);
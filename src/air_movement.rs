@@ -0,0 +1,153 @@
+// Gliding and jetpacks
+use crate::{
+    controller::{CharacterController, MovementMode},
+    look::{LookDirection, LookEntity},
+    probe::Ground,
+};
+use bevy::prelude::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AirMode {
+    None,
+    /// Hold jump while falling to glide
+    Glide,
+    /// Hold jump to thrust upwards while there is fuel
+    Jetpack,
+}
+
+/// Insert on the character body to choose how it moves through the air. Movement goes through
+/// `CharacterController::override_velocity`, so it becomes forces / impulses for the dynamic
+/// backends and translations for the kinematic path. Add `Ground` to the body so that walking off
+/// a ledge counts as being in the air.
+pub struct AirMovement {
+    pub mode: AirMode,
+    /// Maximum falling speed in m/s while gliding
+    pub glide_fall_speed: f32,
+    /// Horizontal speed in m/s reached while gliding forwards
+    pub glide_speed: f32,
+    /// Horizontal acceleration in m/s^2 while gliding or flying with the jetpack
+    pub air_acceleration: f32,
+    /// Upward acceleration in m/s^2 from the jetpack, before gravity
+    pub thrust: f32,
+    /// Maximum upward speed in m/s with the jetpack
+    pub max_rise_speed: f32,
+    /// Horizontal speed in m/s with the jetpack
+    pub jetpack_speed: f32,
+    /// Jetpack fuel in seconds of thrust, burnt and regained in frame time
+    pub max_fuel: f32,
+    pub fuel: f32,
+    /// Fuel regained per second while on the ground
+    pub refuel_rate: f32,
+    /// Whether the character is currently gliding or thrusting
+    pub active: bool,
+}
+
+impl Default for AirMovement {
+    fn default() -> Self {
+        Self {
+            mode: AirMode::Glide,
+            glide_fall_speed: 2.0,
+            glide_speed: 10.0,
+            air_acceleration: 8.0,
+            thrust: 20.0,
+            max_rise_speed: 6.0,
+            jetpack_speed: 6.0,
+            max_fuel: 3.0,
+            fuel: 3.0,
+            refuel_rate: 1.0,
+            active: false,
+        }
+    }
+}
+
+pub fn air_movement(
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut query: Query<(
+        &LookEntity,
+        &mut CharacterController,
+        &mut AirMovement,
+        Option<&Ground>,
+    )>,
+    look_direction_query: Query<&LookDirection>,
+) {
+    let xz = Vec3::new(1.0, 0.0, 1.0);
+    for (look_entity, mut controller, mut air, ground) in query.iter_mut() {
+        // Walking off a ledge leaves `jumping` unset, so the ground probe decides where available
+        let grounded = !controller.jumping && ground.map_or(true, |ground| ground.hit.is_some());
+        if grounded {
+            air.fuel = (air.fuel + air.refuel_rate * time.delta_seconds()).min(air.max_fuel);
+        }
+
+        let input_map = &controller.input_map;
+        let jump_held = keyboard_input.pressed(input_map.key_jump);
+        let mut input = Vec3::zero();
+        if keyboard_input.pressed(input_map.key_forward) {
+            input += Vec3::unit_z();
+        }
+        if keyboard_input.pressed(input_map.key_backward) {
+            input -= Vec3::unit_z();
+        }
+        if keyboard_input.pressed(input_map.key_right) {
+            input += Vec3::unit_x();
+        }
+        if keyboard_input.pressed(input_map.key_left) {
+            input -= Vec3::unit_x();
+        }
+
+        air.active = match air.mode {
+            AirMode::None => false,
+            AirMode::Glide => !grounded && jump_held && controller.velocity.y < 0.0,
+            // Jump from the ground first, then thrust
+            AirMode::Jetpack => !grounded && jump_held && air.fuel > 0.0,
+        };
+        if !air.active {
+            continue;
+        }
+        // Not grounded, so that gravity applies once gliding or thrusting stops
+        controller.jumping = true;
+
+        let look = look_direction_query
+            .get_component::<LookDirection>(look_entity.0)
+            .expect("Failed to get LookDirection from Entity");
+        let forward = (look.forward * xz).normalize();
+        let right = (look.right * xz).normalize();
+        let dt = controller.dt;
+        let mut horizontal = controller.velocity * xz;
+        let mut vertical = controller.velocity.y;
+
+        let desired_horizontal = match air.mode {
+            AirMode::Glide => {
+                vertical = (vertical - controller.gravity * dt).max(-air.glide_fall_speed);
+                // Forward input converts the fall into horizontal speed
+                if input.z > 0.0 {
+                    (forward + right * input.x).normalize() * air.glide_speed
+                } else {
+                    horizontal
+                }
+            }
+            AirMode::Jetpack => {
+                air.fuel -= time.delta_seconds();
+                vertical =
+                    (vertical + (air.thrust - controller.gravity) * dt).min(air.max_rise_speed);
+                if input.length_squared() > 1E-6 {
+                    (forward * input.z + right * input.x).normalize() * air.jetpack_speed
+                } else {
+                    horizontal
+                }
+            }
+            AirMode::None => horizontal,
+        };
+        let change = desired_horizontal - horizontal;
+        let max_change = air.air_acceleration * dt;
+        horizontal += if change.length() > max_change {
+            change.normalize() * max_change
+        } else {
+            change
+        };
+        controller.override_velocity(
+            MovementMode::AirMovement,
+            horizontal + Vec3::unit_y() * vertical,
+        );
+    }
+}
@@ -0,0 +1,351 @@
+// Composes the camera transform from the boom under the head plus any camera effects
+use crate::{
+    camera_rig::BlendCurve,
+    controller::{CameraTag, CharacterController},
+    look::LookEntity,
+};
+use bevy::prelude::*;
+
+pub const CAMERA_EFFECTS: &str = "camera_effects";
+pub const APPLY_CAMERA: &str = "apply_camera";
+
+/// Rest transform of the camera relative to the head, like a camera boom arm stuck to the
+/// character's head. Captured from the camera Transform if not inserted at spawn time.
+#[derive(Clone, Copy, Debug)]
+pub struct CameraBoom {
+    pub translation: Vec3,
+    pub rotation: Quat,
+}
+
+impl CameraBoom {
+    /// A boom at `offset` from the head looking at `focal_point`, both relative to the head
+    pub fn looking_at(offset: Vec3, focal_point: Vec3) -> Self {
+        let transform =
+            Transform::from_matrix(Mat4::face_toward(offset, focal_point, Vec3::unit_y()));
+        Self {
+            translation: transform.translation,
+            rotation: transform.rotation,
+        }
+    }
+}
+
+impl From<&Transform> for CameraBoom {
+    fn from(transform: &Transform) -> Self {
+        Self {
+            translation: transform.translation,
+            rotation: transform.rotation,
+        }
+    }
+}
+
+/// Offsets accumulated by camera effect systems in the `CAMERA_EFFECTS` stage and layered on top
+/// of the `CameraBoom`. Reset every frame after being applied.
+#[derive(Clone, Copy, Debug)]
+pub struct CameraOffset {
+    /// Translation relative to the head
+    pub translation: Vec3,
+    /// Rotation relative to the boom orientation
+    pub rotation: Quat,
+}
+
+impl Default for CameraOffset {
+    fn default() -> Self {
+        Self {
+            translation: Vec3::zero(),
+            rotation: Quat::identity(),
+        }
+    }
+}
+
+impl CameraOffset {
+    pub fn add(&mut self, translation: Vec3, rotation: Quat) {
+        self.translation += translation;
+        self.rotation = self.rotation * rotation;
+    }
+}
+
+/// State of the blend between camera rigs and the default boom, written by `blend_camera_rigs`
+#[derive(Clone, Copy, Debug)]
+pub struct CameraRigBlend {
+    /// The rig being blended to, None for the default boom
+    pub rig: Option<Entity>,
+    /// World space pose the blend started from
+    pub from: (Vec3, Quat),
+    /// World space pose of the camera while a rig is active or being blended from
+    pub pose: Option<(Vec3, Quat)>,
+    /// Time in seconds since the blend started
+    pub elapsed: f32,
+    pub curve: BlendCurve,
+    /// Blend duration in seconds
+    pub duration: f32,
+}
+
+impl Default for CameraRigBlend {
+    fn default() -> Self {
+        Self {
+            rig: None,
+            from: (Vec3::zero(), Quat::identity()),
+            pose: None,
+            elapsed: 0.0,
+            curve: BlendCurve::EaseInOut,
+            duration: 0.0,
+        }
+    }
+}
+
+pub fn init_camera(
+    commands: &mut Commands,
+    query: Query<(Entity, &Transform), (With<CameraTag>, Without<CameraBoom>)>,
+) {
+    for (entity, transform) in query.iter() {
+        commands.insert(
+            entity,
+            (
+                CameraBoom::from(transform),
+                CameraOffset::default(),
+                CameraRigBlend::default(),
+            ),
+        );
+    }
+}
+
+pub fn apply_camera(mut query: Query<(&CameraBoom, &mut CameraOffset, &mut Transform)>) {
+    for (boom, mut offset, mut transform) in query.iter_mut() {
+        transform.translation = boom.translation + offset.translation;
+        transform.rotation = boom.rotation * offset.rotation;
+        *offset = CameraOffset::default();
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ViewMode {
+    FirstPerson,
+    ThirdPerson,
+}
+
+/// Insert on the `CameraTag` entity to switch between a first-person boom at the head and a
+/// third-person boom with the toggle view key. The `CameraBoom` is blended smoothly between the
+/// two. `LookDirection` comes from the look state rather than the camera, so it stays continuous.
+pub struct CameraMode {
+    pub mode: ViewMode,
+    pub first_person: CameraBoom,
+    pub third_person: CameraBoom,
+    /// Time in seconds to transition between modes
+    pub transition_time: f32,
+    /// Transition progress, 0 is first person and 1 is third person
+    pub blend: f32,
+}
+
+impl Default for CameraMode {
+    fn default() -> Self {
+        Self::new(
+            ViewMode::ThirdPerson,
+            CameraBoom::looking_at(Vec3::zero(), -Vec3::unit_z()),
+            CameraBoom::looking_at(Vec3::new(0.0, 4.0, 8.0), Vec3::zero()),
+        )
+    }
+}
+
+impl CameraMode {
+    pub fn new(mode: ViewMode, first_person: CameraBoom, third_person: CameraBoom) -> Self {
+        Self {
+            mode,
+            first_person,
+            third_person,
+            transition_time: 0.4,
+            blend: if mode == ViewMode::ThirdPerson {
+                1.0
+            } else {
+                0.0
+            },
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.mode = match self.mode {
+            ViewMode::FirstPerson => ViewMode::ThirdPerson,
+            ViewMode::ThirdPerson => ViewMode::FirstPerson,
+        };
+    }
+
+    pub fn boom(&self) -> CameraBoom {
+        let t = self.blend * self.blend * (3.0 - 2.0 * self.blend);
+        CameraBoom {
+            translation: self
+                .first_person
+                .translation
+                .lerp(self.third_person.translation, t),
+            rotation: self
+                .first_person
+                .rotation
+                .slerp(self.third_person.rotation, t),
+        }
+    }
+}
+
+/// Tag models, e.g. the body and head models, that should be hidden in first person. Only the
+/// models in the hierarchy under the body of the character being viewed are hidden.
+pub struct HideInFirstPerson;
+
+pub fn toggle_camera_mode(
+    keyboard_input: Res<Input<KeyCode>>,
+    controller_query: Query<(&CharacterController, &LookEntity)>,
+    mut camera_query: Query<&mut CameraMode>,
+) {
+    for (controller, look_entity) in controller_query.iter() {
+        if keyboard_input.just_pressed(controller.input_map.key_toggle_view) {
+            if let Ok(mut camera_mode) = camera_query.get_mut(look_entity.0) {
+                camera_mode.toggle();
+            }
+        }
+    }
+}
+
+pub fn camera_mode_to_boom(
+    time: Res<Time>,
+    mut camera_query: Query<(Entity, &mut CameraMode, &mut CameraBoom)>,
+    mut model_query: Query<(Entity, &mut Visible), With<HideInFirstPerson>>,
+    parent_query: Query<&Parent>,
+    look_entity_query: Query<&LookEntity>,
+) {
+    for (camera, mut camera_mode, mut boom) in camera_query.iter_mut() {
+        let target = match camera_mode.mode {
+            ViewMode::FirstPerson => 0.0,
+            ViewMode::ThirdPerson => 1.0,
+        };
+        let step = time.delta_seconds() / camera_mode.transition_time.max(1E-3);
+        camera_mode.blend = if (target - camera_mode.blend).abs() <= step {
+            target
+        } else {
+            camera_mode.blend + step * (target - camera_mode.blend).signum()
+        };
+        *boom = camera_mode.boom();
+
+        // Hide the models of this camera's character once the camera is close enough to the head
+        // to clip into them
+        let visible = camera_mode.blend > 0.1;
+        for (model_entity, mut model) in model_query.iter_mut() {
+            if character_camera(model_entity, &parent_query, &look_entity_query) == Some(camera) {
+                model.is_visible = visible;
+            }
+        }
+    }
+}
+
+/// The camera of the character that `entity` is part of, found through the `LookEntity` of the
+/// nearest ancestor that has one
+fn character_camera(
+    entity: Entity,
+    parent_query: &Query<&Parent>,
+    look_entity_query: &Query<&LookEntity>,
+) -> Option<Entity> {
+    let mut current = Some(entity);
+    while let Some(entity) = current {
+        if let Ok(look_entity) = look_entity_query.get(entity) {
+            return Some(look_entity.0);
+        }
+        current = parent_query.get(entity).ok().map(|parent| parent.0);
+    }
+    None
+}
+
+/// Insert on the `CameraTag` entity for an over-the-shoulder offset of a third-person boom. The
+/// shoulder can be swapped with the swap shoulder key and is swapped automatically when the
+/// physics backends report that the current shoulder is blocked.
+pub struct ShoulderOffset {
+    /// Lateral offset in metres
+    pub offset: f32,
+    /// 1 for the right shoulder, -1 for the left
+    pub side: f32,
+    /// Rate at which the lateral offset follows its target, per second
+    pub speed: f32,
+    /// Radius kept clear around the camera when checking for obstacles
+    pub radius: f32,
+    /// Swap shoulders when the current one is blocked and the other is clearer
+    pub auto_swap: bool,
+    /// Current lateral offset in metres, negative is left
+    pub current: f32,
+    /// Free space in metres to the left of the head, written by the physics backend
+    pub clearance_left: f32,
+    /// Free space in metres to the right of the head, written by the physics backend
+    pub clearance_right: f32,
+    /// World space position of the head, for obstacle checks
+    pub origin: Vec3,
+    /// World space right direction of the head, for obstacle checks
+    pub right: Vec3,
+}
+
+impl Default for ShoulderOffset {
+    fn default() -> Self {
+        Self {
+            offset: 0.75,
+            side: 1.0,
+            speed: 6.0,
+            radius: 0.2,
+            auto_swap: true,
+            current: 0.0,
+            clearance_left: f32::MAX,
+            clearance_right: f32::MAX,
+            origin: Vec3::zero(),
+            right: Vec3::unit_x(),
+        }
+    }
+}
+
+impl ShoulderOffset {
+    pub fn swap(&mut self) {
+        self.side = -self.side;
+    }
+
+    fn clearance(&self, side: f32) -> f32 {
+        let clearance = if side > 0.0 {
+            self.clearance_right
+        } else {
+            self.clearance_left
+        };
+        (clearance - self.radius).max(0.0)
+    }
+}
+
+pub fn shoulder_offset(
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
+    controller_query: Query<(&CharacterController, &LookEntity)>,
+    mut camera_query: Query<(
+        &mut ShoulderOffset,
+        &mut CameraOffset,
+        &Parent,
+        Option<&CameraMode>,
+    )>,
+    head_query: Query<&GlobalTransform>,
+) {
+    for (controller, look_entity) in controller_query.iter() {
+        if keyboard_input.just_pressed(controller.input_map.key_swap_shoulder) {
+            if let Ok((mut shoulder, _, _, _)) = camera_query.get_mut(look_entity.0) {
+                shoulder.swap();
+            }
+        }
+    }
+
+    for (mut shoulder, mut offset, parent, camera_mode) in camera_query.iter_mut() {
+        if shoulder.auto_swap
+            && shoulder.clearance(shoulder.side) < shoulder.offset
+            && shoulder.clearance(-shoulder.side) > shoulder.clearance(shoulder.side)
+        {
+            shoulder.swap();
+        }
+
+        // No shoulder offset in first person
+        let third_person = camera_mode.map_or(1.0, |camera_mode| camera_mode.blend);
+        let target =
+            shoulder.side * shoulder.offset.min(shoulder.clearance(shoulder.side)) * third_person;
+        let step = (shoulder.speed * time.delta_seconds()).min(1.0);
+        shoulder.current += (target - shoulder.current) * step;
+        offset.add(shoulder.current * Vec3::unit_x(), Quat::identity());
+
+        if let Ok(head_transform) = head_query.get(parent.0) {
+            shoulder.origin = head_transform.translation;
+            shoulder.right = head_transform.rotation * Vec3::unit_x();
+        }
+    }
+}
@@ -0,0 +1,214 @@
+// Camera rigs that override the camera boom, activated by trigger volumes and blended by priority
+use crate::{camera::CameraRigBlend, controller::BodyTag, volume::contains_point};
+use bevy::{prelude::*, utils::HashMap};
+
+pub const BLEND_CAMERA_RIGS: &str = "blend_camera_rigs";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlendCurve {
+    Cut,
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl BlendCurve {
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.max(0.0).min(1.0);
+        match self {
+            BlendCurve::Cut => 1.0,
+            BlendCurve::Linear => t,
+            BlendCurve::EaseIn => t * t,
+            BlendCurve::EaseOut => t * (2.0 - t),
+            BlendCurve::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum RigKind {
+    /// Fixed angle at the rig entity's GlobalTransform
+    Fixed,
+    /// Side-scroller rail through the rig entity's position along `axis`. The camera follows the
+    /// character's projection onto the rail at `offset` and looks at the character
+    Rail { axis: Vec3, offset: Vec3 },
+    /// Top-down view at `offset` from the character, looking at the character
+    TopDown { offset: Vec3 },
+}
+
+/// A camera rig entity. The active rig with the highest priority overrides the camera, falling
+/// back to the default boom from the character hierarchy when no rig is active.
+pub struct CameraRig {
+    pub kind: RigKind,
+    pub priority: i32,
+    pub active: bool,
+    /// Curve used when blending to this rig and back from it to the default boom
+    pub blend_curve: BlendCurve,
+    /// Blend duration in seconds
+    pub blend_duration: f32,
+}
+
+impl Default for CameraRig {
+    fn default() -> Self {
+        Self {
+            kind: RigKind::Fixed,
+            priority: 0,
+            active: false,
+            blend_curve: BlendCurve::EaseInOut,
+            blend_duration: 1.0,
+        }
+    }
+}
+
+impl CameraRig {
+    /// World space translation and rotation of the rig given its transform and the focus point
+    fn pose(&self, rig_transform: &GlobalTransform, focus: Vec3) -> (Vec3, Quat) {
+        let look_at = |eye: Vec3, up: Vec3| {
+            let transform = Transform::from_matrix(Mat4::face_toward(eye, focus, up));
+            (eye, transform.rotation)
+        };
+        match self.kind {
+            RigKind::Fixed => (rig_transform.translation, rig_transform.rotation),
+            RigKind::Rail { axis, offset } => {
+                let origin = rig_transform.translation;
+                let axis = axis.normalize();
+                let on_rail = origin + axis * axis.dot(focus - origin);
+                look_at(on_rail + offset, Vec3::unit_y())
+            }
+            // NOTE: -z as up so that looking straight down is well defined
+            RigKind::TopDown { offset } => look_at(focus + offset, -Vec3::unit_z()),
+        }
+    }
+}
+
+/// An axis-aligned trigger volume, in the space of its entity's GlobalTransform, that activates
+/// `rig` while a character body is inside it
+pub struct CameraVolume {
+    pub half_extents: Vec3,
+    pub rig: Entity,
+}
+
+impl CameraVolume {
+    pub fn contains(&self, volume_transform: &GlobalTransform, point: Vec3) -> bool {
+        contains_point(self.half_extents, volume_transform, point)
+    }
+}
+
+pub fn camera_volumes(
+    volume_query: Query<(&CameraVolume, &GlobalTransform)>,
+    body_query: Query<&GlobalTransform, With<BodyTag>>,
+    mut rig_query: Query<&mut CameraRig>,
+) {
+    let mut occupied = HashMap::new();
+    for (volume, volume_transform) in volume_query.iter() {
+        let inside = body_query
+            .iter()
+            .any(|body_transform| volume.contains(volume_transform, body_transform.translation));
+        *occupied.entry(volume.rig).or_insert(false) |= inside;
+    }
+    for (rig_entity, inside) in occupied {
+        if let Ok(mut rig) = rig_query.get_mut(rig_entity) {
+            rig.active = inside;
+        }
+    }
+}
+
+pub fn blend_camera_rigs(
+    time: Res<Time>,
+    mut camera_query: Query<(&mut CameraRigBlend, &mut Transform, &Parent)>,
+    rig_query: Query<(Entity, &CameraRig, &GlobalTransform)>,
+    head_query: Query<&GlobalTransform>,
+) {
+    let best = rig_query
+        .iter()
+        .filter(|(_, rig, _)| rig.active)
+        .max_by_key(|(_, rig, _)| rig.priority)
+        .map(|(entity, _, _)| entity);
+
+    for (mut state, mut transform, parent) in camera_query.iter_mut() {
+        let head_transform = match head_query.get(parent.0) {
+            Ok(head_transform) => *head_transform,
+            Err(_) => continue,
+        };
+        let default_pose = (
+            head_transform.translation + head_transform.rotation * transform.translation,
+            head_transform.rotation * transform.rotation,
+        );
+
+        if best != state.rig {
+            // Blend with the settings of the rig being blended to, or from when returning to
+            // the default boom
+            let settings_rig = best.or(state.rig);
+            if let Some((_, rig, _)) = settings_rig.and_then(|rig| rig_query.get(rig).ok()) {
+                state.curve = rig.blend_curve;
+                state.duration = rig.blend_duration;
+            }
+            state.from = state.pose.unwrap_or(default_pose);
+            state.elapsed = 0.0;
+            state.rig = best;
+        }
+        state.elapsed += time.delta_seconds();
+
+        let target = match state.rig.and_then(|rig| rig_query.get(rig).ok()) {
+            Some((_, rig, rig_transform)) => rig.pose(rig_transform, head_transform.translation),
+            None => default_pose,
+        };
+        let t = if state.duration > 0.0 {
+            state.curve.apply(state.elapsed / state.duration)
+        } else {
+            1.0
+        };
+        if state.rig.is_none() && t >= 1.0 {
+            // Fully back on the default boom
+            state.pose = None;
+            continue;
+        }
+
+        let pose = (
+            state.from.0.lerp(target.0, t),
+            state.from.1.slerp(target.1, t),
+        );
+        state.pose = Some(pose);
+
+        // Convert the world space pose back to the space of the head
+        let inverse_rotation = head_transform.rotation.conjugate();
+        transform.translation = inverse_rotation * (pose.0 - head_transform.translation);
+        transform.rotation = inverse_rotation * pose.1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blend_curves_start_and_end_at_the_targets() {
+        for curve in [
+            BlendCurve::Linear,
+            BlendCurve::EaseIn,
+            BlendCurve::EaseOut,
+            BlendCurve::EaseInOut,
+        ]
+        .iter()
+        {
+            assert_eq!(curve.apply(0.0), 0.0);
+            assert_eq!(curve.apply(1.0), 1.0);
+        }
+        assert_eq!(BlendCurve::Cut.apply(0.0), 1.0);
+    }
+
+    #[test]
+    fn blend_curves_clamp_t() {
+        assert_eq!(BlendCurve::Linear.apply(-1.0), 0.0);
+        assert_eq!(BlendCurve::Linear.apply(2.0), 1.0);
+    }
+
+    #[test]
+    fn blend_curves_shape() {
+        assert!((BlendCurve::Linear.apply(0.5) - 0.5).abs() < 1E-6);
+        assert!(BlendCurve::EaseIn.apply(0.5) < 0.5);
+        assert!(BlendCurve::EaseOut.apply(0.5) > 0.5);
+        assert!((BlendCurve::EaseInOut.apply(0.5) - 0.5).abs() < 1E-6);
+    }
+}
@@ -0,0 +1,237 @@
+// Ladders and other climbable surfaces
+use crate::{
+    controller::{CharacterController, MovementMode},
+    look::{LookDirection, LookEntity},
+    modifiers::SpeedModifiers,
+    stamina::Stamina,
+    volume::contains_point,
+};
+use bevy::prelude::*;
+
+/// Insert on a ladder or other climbable collider. The climbable's local y axis is the direction
+/// to climb and its local z axis points out of the side it is climbed from. `half_extents` should
+/// match the collider, in the entity's space.
+pub struct Climbable {
+    pub half_extents: Vec3,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClimbState {
+    None,
+    Climbing,
+    /// Moving from the top of the climbable onto the surface above it
+    Exiting,
+}
+
+/// Height in metres above the bottom of a climbable within which moving backward steps off it
+const STEP_OFF_HEIGHT: f32 = 0.1;
+
+/// Insert on the character body. Walking into the front of a `Climbable` while facing it starts
+/// climbing: forward / backward
+/// move along the climbable's up axis, left / right move across it and gravity is disabled.
+/// Jump detaches with a push off and reaching the top moves the character onto the ledge.
+pub struct Climber {
+    /// Distance in metres from a climbable at which climbing starts
+    pub reach: f32,
+    /// Maximum angle in radians between the look direction and the front of a climbable at which
+    /// climbing starts
+    pub grab_angle: f32,
+    /// Climbing speed in m/s
+    pub speed: f32,
+    /// Speed in m/s away from the climbable when jumping off
+    pub push_off_speed: f32,
+    /// Upward speed in m/s when jumping off
+    pub push_off_up_speed: f32,
+    /// Height of the body origin above the feet
+    pub feet_offset: f32,
+    /// Time in seconds to move onto the ledge at the top
+    pub exit_duration: f32,
+    /// Time in seconds after detaching before climbing can start again
+    pub reattach_delay: f32,
+    /// Current state, e.g. for animation
+    pub state: ClimbState,
+    /// The climbable being climbed
+    pub climbable: Option<Entity>,
+    exit_from: Vec3,
+    exit_to: Vec3,
+    elapsed: f32,
+    reattach_remaining: f32,
+}
+
+impl Default for Climber {
+    fn default() -> Self {
+        Self {
+            reach: 0.5,
+            grab_angle: std::f32::consts::FRAC_PI_3,
+            speed: 2.5,
+            push_off_speed: 4.0,
+            push_off_up_speed: 3.0,
+            feet_offset: 1.2,
+            exit_duration: 0.5,
+            reattach_delay: 0.5,
+            state: ClimbState::None,
+            climbable: None,
+            exit_from: Vec3::zero(),
+            exit_to: Vec3::zero(),
+            elapsed: 0.0,
+            reattach_remaining: 0.0,
+        }
+    }
+}
+
+/// Position of `point` in the space of the climbable
+fn to_local(transform: &GlobalTransform, point: Vec3) -> Vec3 {
+    transform.rotation.conjugate() * (point - transform.translation)
+}
+
+pub fn climb(
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut query: Query<(
+        &GlobalTransform,
+        &LookEntity,
+        &mut CharacterController,
+        &mut Climber,
+        Option<&Stamina>,
+        Option<&SpeedModifiers>,
+    )>,
+    look_direction_query: Query<&LookDirection>,
+    climbable_query: Query<(Entity, &Climbable, &GlobalTransform)>,
+) {
+    let dt = time.delta_seconds();
+    let xz = Vec3::new(1.0, 0.0, 1.0);
+    for (transform, look_entity, mut controller, mut climber, stamina, modifiers) in
+        query.iter_mut()
+    {
+        let position = transform.translation;
+        climber.reattach_remaining -= dt;
+        let input_map = &controller.input_map;
+        let forward = keyboard_input.pressed(input_map.key_forward);
+        let backward = keyboard_input.pressed(input_map.key_backward);
+        let left = keyboard_input.pressed(input_map.key_left);
+        let right = keyboard_input.pressed(input_map.key_right);
+        let jump = keyboard_input.just_pressed(input_map.key_jump)
+            && stamina.map_or(true, |stamina| stamina.can_jump());
+
+        match climber.state {
+            ClimbState::None => {
+                let rooted = modifiers.map_or(false, |modifiers| modifiers.rooted());
+                if !forward || rooted || climber.reattach_remaining > 0.0 {
+                    continue;
+                }
+                let look = look_direction_query
+                    .get_component::<LookDirection>(look_entity.0)
+                    .expect("Failed to get LookDirection from Entity");
+                let facing = (look.forward * xz).normalize();
+                let reach = climber.reach;
+                let min_facing = climber.grab_angle.cos();
+                climber.climbable = climbable_query
+                    .iter()
+                    .find(|(_, climbable, climbable_transform)| {
+                        let extents = climbable.half_extents + Vec3::splat(reach);
+                        // Only grab from the front, while facing it
+                        let normal = climbable_transform.rotation * Vec3::unit_z();
+                        to_local(climbable_transform, position).z >= 0.0
+                            && facing.dot(-normal) >= min_facing
+                            && contains_point(extents, climbable_transform, position)
+                    })
+                    .map(|(entity, _, _)| entity);
+                if climber.climbable.is_some() {
+                    climber.state = ClimbState::Climbing;
+                }
+            }
+            ClimbState::Climbing => {
+                let (climbable, climbable_transform) = match climber
+                    .climbable
+                    .and_then(|entity| climbable_query.get(entity).ok())
+                {
+                    Some((_, climbable, climbable_transform)) => (climbable, climbable_transform),
+                    None => {
+                        climber.state = ClimbState::None;
+                        climber.climbable = None;
+                        continue;
+                    }
+                };
+                let up = climbable_transform.rotation * Vec3::unit_y();
+                let across = climbable_transform.rotation * Vec3::unit_x();
+                let normal = climbable_transform.rotation * Vec3::unit_z();
+
+                if jump {
+                    climber.state = ClimbState::None;
+                    climber.reattach_remaining = climber.reattach_delay;
+                    controller.jumping = true;
+                    controller.jumped = true;
+                    controller.set_velocity_once(
+                        MovementMode::Climb,
+                        normal * climber.push_off_speed
+                            + Vec3::unit_y() * climber.push_off_up_speed,
+                    );
+                    continue;
+                }
+
+                let local = to_local(climbable_transform, position);
+                let feet = local.y - climber.feet_offset;
+                if feet >= climbable.half_extents.y && forward {
+                    // Reached the top, move onto the ledge
+                    let top = climbable_transform.translation + up * climbable.half_extents.y;
+                    climber.state = ClimbState::Exiting;
+                    climber.elapsed = 0.0;
+                    climber.exit_from = position;
+                    climber.exit_to = top + up * climber.feet_offset
+                        - normal * (climbable.half_extents.z + climber.reach);
+                    continue;
+                }
+                if !controller.jumping
+                    && backward
+                    && !forward
+                    && feet <= -climbable.half_extents.y + STEP_OFF_HEIGHT
+                {
+                    // Stepped off at the bottom
+                    climber.state = ClimbState::None;
+                    climber.reattach_remaining = climber.reattach_delay;
+                    continue;
+                }
+
+                let mut velocity = Vec3::zero();
+                if forward {
+                    velocity += up;
+                }
+                if backward {
+                    velocity -= up;
+                }
+                if right {
+                    velocity += across;
+                }
+                if left {
+                    velocity -= across;
+                }
+                if velocity.length_squared() > 1E-6 {
+                    velocity = velocity.normalize() * climber.speed;
+                }
+                // Zero velocity otherwise, which also cancels gravity
+                controller.override_velocity(MovementMode::Climb, velocity);
+            }
+            ClimbState::Exiting => {
+                climber.elapsed += dt;
+                let duration = climber.exit_duration.max(1E-3);
+                let t = climber.elapsed / duration;
+                if t >= 1.0 {
+                    climber.state = ClimbState::None;
+                    climber.climbable = None;
+                    controller.jumping = false;
+                    controller.set_velocity_once(MovementMode::Climb, Vec3::zero());
+                    continue;
+                }
+                // Rise first, then move over the top
+                let t = (t + controller.dt / duration).min(1.0);
+                let delta = climber.exit_to - climber.exit_from;
+                let rise = (t / 0.6).min(1.0);
+                let across = ((t - 0.4) / 0.6).max(0.0);
+                let next = climber.exit_from
+                    + Vec3::new(delta.x * across, delta.y * rise, delta.z * across);
+                let velocity = (next - position) / controller.dt;
+                controller.override_velocity(MovementMode::Climb, velocity);
+            }
+        }
+    }
+}
@@ -5,44 +5,121 @@
  */
 
 use crate::{
+    air_movement::air_movement,
+    camera::{
+        apply_camera, camera_mode_to_boom, init_camera, shoulder_offset, toggle_camera_mode,
+        APPLY_CAMERA, CAMERA_EFFECTS,
+    },
+    camera_rig::{blend_camera_rigs, camera_volumes, BLEND_CAMERA_RIGS},
+    climb::climb,
+    dash::dash,
     events::{
         ControllerEvents, ForceEvent, ImpulseEvent, LookDeltaEvent, LookEvent, PitchEvent,
-        TranslationEvent, YawEvent,
+        RollEvent, StaminaDepletedEvent, StaminaRecoveredEvent, TranslationEvent, TraumaEvent,
+        YawEvent,
     },
+    external::ExternalVelocity,
+    force_field::force_fields,
+    fov::fov_kick,
+    head_bob::head_bob,
     input_map::InputMap,
+    jump_pad::jump_pads,
+    lean::{input_to_lean, lean_to_head, Lean},
+    ledge::ledge_grab,
+    lock_on::{lock_on, LockOn},
     look::{forward_up, input_to_look, LookDirection, LookEntity, MouseMotionState, MouseSettings},
+    modifiers::{speed_modifiers, MovementSpeeds, SpeedModifiers},
+    platform::moving_platforms,
+    probe::Ground,
+    shake::{camera_shake, CameraShakeState},
+    slide::{crouch_speed, slide, slide_head, Slide},
+    stamina::{stamina, Stamina},
+    surface::{SurfaceFriction, SurfaceVelocity},
+    swim::swim,
+    vault::vault,
+    wall_run::{wall_run, wall_run_camera},
 };
-use bevy::prelude::*;
+use bevy::{prelude::*, utils::HashMap};
 
 pub struct BodyTag;
 pub struct YawTag;
 pub struct HeadTag;
 pub struct CameraTag;
 
+pub struct HeadEntity(pub Entity);
+
 pub struct CharacterControllerPlugin;
 
 pub const PROCESS_INPUT_EVENTS: &str = "process_input_events";
+pub const MOVEMENT_MODES: &str = "movement_modes";
 
 impl Plugin for CharacterControllerPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.add_event::<PitchEvent>()
             .add_event::<YawEvent>()
+            .add_event::<RollEvent>()
             .add_event::<LookEvent>()
             .add_event::<LookDeltaEvent>()
             .add_event::<TranslationEvent>()
             .add_event::<ImpulseEvent>()
             .add_event::<ForceEvent>()
+            .add_event::<TraumaEvent>()
+            .add_event::<StaminaDepletedEvent>()
+            .add_event::<StaminaRecoveredEvent>()
             .init_resource::<ControllerEvents>()
             .init_resource::<MouseMotionState>()
             .init_resource::<MouseSettings>()
+            .init_resource::<CameraShakeState>()
             .add_stage_after(
                 bevy::app::stage::PRE_UPDATE,
                 PROCESS_INPUT_EVENTS,
                 SystemStage::parallel(),
             )
+            .add_stage_before(
+                PROCESS_INPUT_EVENTS,
+                MOVEMENT_MODES,
+                SystemStage::parallel(),
+            )
+            .add_system_to_stage(MOVEMENT_MODES, dash.system())
+            .add_system_to_stage(MOVEMENT_MODES, wall_run.system())
+            .add_system_to_stage(MOVEMENT_MODES, ledge_grab.system())
+            .add_system_to_stage(MOVEMENT_MODES, vault.system())
+            .add_system_to_stage(MOVEMENT_MODES, climb.system())
+            .add_system_to_stage(MOVEMENT_MODES, swim.system())
+            .add_system_to_stage(MOVEMENT_MODES, moving_platforms.system())
+            .add_system_to_stage(MOVEMENT_MODES, jump_pads.system())
+            .add_system_to_stage(MOVEMENT_MODES, force_fields.system())
+            .add_system_to_stage(MOVEMENT_MODES, speed_modifiers.system())
+            .add_system_to_stage(MOVEMENT_MODES, slide.system())
+            .add_system_to_stage(MOVEMENT_MODES, crouch_speed.system())
+            .add_system_to_stage(MOVEMENT_MODES, air_movement.system())
             .add_system_to_stage(PROCESS_INPUT_EVENTS, input_to_events.system())
             .add_system_to_stage(PROCESS_INPUT_EVENTS, input_to_look.system())
-            .add_system_to_stage(PROCESS_INPUT_EVENTS, forward_up.system());
+            .add_system_to_stage(PROCESS_INPUT_EVENTS, forward_up.system())
+            .add_system_to_stage(PROCESS_INPUT_EVENTS, input_to_lean.system())
+            .add_system_to_stage(bevy::app::stage::UPDATE, lean_to_head.system())
+            .add_system_to_stage(bevy::app::stage::UPDATE, slide_head.system())
+            .add_system_to_stage(PROCESS_INPUT_EVENTS, toggle_camera_mode.system())
+            .add_system_to_stage(PROCESS_INPUT_EVENTS, lock_on.system())
+            .add_system_to_stage(bevy::app::stage::UPDATE, camera_mode_to_boom.system())
+            .add_system_to_stage(bevy::app::stage::UPDATE, stamina.system())
+            .add_stage_after(
+                bevy::app::stage::UPDATE,
+                CAMERA_EFFECTS,
+                SystemStage::parallel(),
+            )
+            .add_stage_after(CAMERA_EFFECTS, APPLY_CAMERA, SystemStage::parallel())
+            .add_stage_after(APPLY_CAMERA, BLEND_CAMERA_RIGS, SystemStage::parallel())
+            .add_system_to_stage(bevy::app::stage::PRE_UPDATE, init_camera.system())
+            .add_system_to_stage(CAMERA_EFFECTS, head_bob.system())
+            .add_system_to_stage(CAMERA_EFFECTS, camera_shake.system())
+            .add_system_to_stage(CAMERA_EFFECTS, fov_kick.system())
+            .add_system_to_stage(CAMERA_EFFECTS, shoulder_offset.system())
+            .add_system_to_stage(CAMERA_EFFECTS, wall_run_camera.system())
+            .add_system_to_stage(CAMERA_EFFECTS, head_translation.system())
+            .add_system_to_stage(APPLY_CAMERA, apply_camera.system())
+            .add_system_to_stage(bevy::app::stage::UPDATE, camera_volumes.system())
+            .add_system_to_stage(BLEND_CAMERA_RIGS, blend_camera_rigs.system());
     }
 }
 
@@ -62,11 +139,24 @@ pub struct CharacterController {
     pub walk_speed: f32,
     pub run_speed: f32,
     pub jump_speed: f32,
+    /// Gravity in m/s^2 for kinematic simulation. The dynamic backends use the gravity of the
+    /// physics world, which should match so that velocity overrides can cancel it
+    pub gravity: f32,
     pub velocity: Vec3,
     pub jumping: bool,
+    /// Set when a jump takes off, from the ground or by movement modes such as jumping off a
+    /// wall, so that `input_to_events` charges it to `Stamina` once in the next simulation step
+    pub jumped: bool,
     pub dt: f32,
     pub sim_to_render: f32,
     pub input_state: InputState,
+    /// Velocity that replaces the input driven velocity for the next simulation step. Set by
+    /// movement modes such as dashing in the `MOVEMENT_MODES` stage through `override_velocity`
+    /// and `set_velocity_once`
+    pub velocity_override: Option<VelocityOverride>,
+    /// Velocity of the ground under the character, e.g. a moving platform. Walking is relative to
+    /// it
+    pub ground_velocity: Vec3,
 }
 
 impl Default for CharacterController {
@@ -77,11 +167,84 @@ impl Default for CharacterController {
             walk_speed: 5.0,
             run_speed: 8.0,
             jump_speed: 6.0,
+            gravity: 9.81,
             velocity: Vec3::zero(),
             jumping: false,
+            jumped: false,
             dt: 1.0 / 60.0,
             sim_to_render: 0.0,
             input_state: InputState::default(),
+            velocity_override: None,
+            ground_velocity: Vec3::zero(),
+        }
+    }
+}
+
+/// Movement modes that take over the character's velocity, in increasing priority. When several
+/// modes request a velocity in the same frame, the one with the highest priority wins.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MovementMode {
+    AirMovement,
+    Swim,
+    Slide,
+    WallRun,
+    Dash,
+    Vault,
+    Climb,
+    LedgeGrab,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct VelocityOverride {
+    pub mode: MovementMode,
+    pub velocity: Vec3,
+    /// Keep the override until a simulation step uses it rather than dropping it at the end of
+    /// the frame
+    pub until_step: bool,
+}
+
+impl CharacterController {
+    /// Take over the velocity for this frame. Modes call this every frame while they are active,
+    /// so the override ends as soon as they stop. While not `jumping` the velocity is relative to
+    /// `ground_velocity`.
+    pub fn override_velocity(&mut self, mode: MovementMode, velocity: Vec3) {
+        self.request_override(VelocityOverride {
+            mode,
+            velocity,
+            until_step: false,
+        });
+    }
+
+    /// Set the velocity once at the next simulation step, e.g. when jumping off a wall
+    pub fn set_velocity_once(&mut self, mode: MovementMode, velocity: Vec3) {
+        self.request_override(VelocityOverride {
+            mode,
+            velocity,
+            until_step: true,
+        });
+    }
+
+    /// The mode that currently overrides the velocity, if any
+    pub fn active_mode(&self) -> Option<MovementMode> {
+        self.velocity_override
+            .map(|velocity_override| velocity_override.mode)
+    }
+
+    fn request_override(&mut self, request: VelocityOverride) {
+        let replace = self
+            .velocity_override
+            .map_or(true, |current| request.mode >= current.mode);
+        if replace {
+            self.velocity_override = Some(request);
+        }
+    }
+
+    /// The configured speeds, before any `SpeedModifiers`
+    pub fn speeds(&self) -> MovementSpeeds {
+        MovementSpeeds {
+            walk: self.walk_speed,
+            run: self.run_speed,
+            jump: self.jump_speed,
         }
     }
 }
@@ -102,11 +265,22 @@ pub fn input_to_events(
     mut translation_events: ResMut<Events<TranslationEvent>>,
     mut impulse_events: ResMut<Events<ImpulseEvent>>,
     mut force_events: ResMut<Events<ForceEvent>>,
-    mut controller_query: Query<(&Mass, &LookEntity, &mut CharacterController)>,
+    mut controller_query: Query<(
+        &Mass,
+        &LookEntity,
+        &mut CharacterController,
+        Option<&LockOn>,
+        Option<&Ground>,
+        Option<&mut ExternalVelocity>,
+        Option<&mut Stamina>,
+        Option<&SpeedModifiers>,
+    )>,
     look_direction_query: Query<&LookDirection>,
+    surface_query: Query<(Option<&SurfaceVelocity>, Option<&SurfaceFriction>)>,
 ) {
-    let xz = Vec3::new(1.0, 0.0, 1.0);
-    for (mass, look_entity, mut controller) in controller_query.iter_mut() {
+    for (mass, look_entity, mut controller, lock_on, ground, external, mut stamina, modifiers) in
+        controller_query.iter_mut()
+    {
         controller.sim_to_render += time.delta_seconds();
 
         if keyboard_input.pressed(controller.input_map.key_forward) {
@@ -121,74 +295,129 @@ pub fn input_to_events(
         if keyboard_input.pressed(controller.input_map.key_left) {
             controller.input_state.left = true;
         }
-        if keyboard_input.pressed(controller.input_map.key_run) {
+        if keyboard_input.pressed(controller.input_map.key_run)
+            && stamina
+                .as_ref()
+                .map_or(true, |stamina| stamina.can_sprint())
+        {
             controller.input_state.run = true;
         }
-        if keyboard_input.just_pressed(controller.input_map.key_jump) {
+        if keyboard_input.just_pressed(controller.input_map.key_jump)
+            && stamina.as_ref().map_or(true, |stamina| stamina.can_jump())
+        {
             controller.input_state.jump = true;
         }
 
         if controller.sim_to_render < controller.dt {
+            // Overrides only last for the frame they were set in unless they wait for a step
+            let until_step = controller
+                .velocity_override
+                .map_or(false, |velocity_override| velocity_override.until_step);
+            if !until_step {
+                controller.velocity_override = None;
+            }
             continue;
         }
         // Calculate the remaining simulation to render time after all
         // simulation steps were taken
         controller.sim_to_render %= controller.dt;
 
+        if let Some(stamina) = stamina.as_mut() {
+            let input = &controller.input_state;
+            let moving = input.forward || input.backward || input.left || input.right;
+            if input.run && moving {
+                let drain = stamina.drain_rate * controller.dt;
+                stamina.drain(drain);
+            }
+        }
+
         let look = look_direction_query
             .get_component::<LookDirection>(look_entity.0)
             .expect("Failed to get LookDirection from Entity");
 
-        // Calculate forward / right / up vectors
-        let (forward, right, _up) = if controller.fly {
-            (look.forward, look.right, look.up)
-        } else {
-            (
-                (look.forward * xz).normalize(),
-                (look.right * xz).normalize(),
-                Vec3::unit_y(),
-            )
+        let velocity_override = controller
+            .velocity_override
+            .take()
+            .map(|velocity_override| velocity_override.velocity);
+        let launching = external
+            .as_ref()
+            .map_or(false, |external| external.launching());
+        // External velocity from the previous step is replaced by the current one. Overrides
+        // replace the whole velocity so nothing external is added while they are active
+        let (previous_external, external) = match external {
+            Some(mut external) => {
+                if !controller.jumping {
+                    external.land();
+                }
+                if external.control_lock > 0.0 {
+                    controller.input_state = InputState::default();
+                }
+                external.update(controller.dt, velocity_override.is_none())
+            }
+            None => (Vec3::zero(), Vec3::zero()),
         };
-
-        // Calculate the desired velocity based on input
-        let mut desired_velocity = Vec3::zero();
-        if controller.input_state.forward {
-            desired_velocity += forward;
-        }
-        if controller.input_state.backward {
-            desired_velocity -= forward;
-        }
-        if controller.input_state.right {
-            desired_velocity += right;
-        }
-        if controller.input_state.left {
-            desired_velocity -= right;
-        }
-
-        // Limit x/z velocity to walk/run speed
-        let speed = if controller.input_state.run {
-            controller.run_speed
-        } else {
-            controller.walk_speed
-        };
-        desired_velocity = if desired_velocity.length_squared() > 1E-6 {
-            desired_velocity.normalize() * speed
-        } else {
-            // No input - apply damping to the x/z of the current velocity
-            controller.velocity * 0.5 * xz
+        let (delta_velocity, velocity) = match velocity_override {
+            // Movement modes such as dashing take over the whole velocity for this step. Overrides
+            // already include gravity, so the gravity the dynamic backends add is cancelled
+            Some(velocity) => {
+                // On the ground, overrides are relative to it so that platforms carry the character
+                let velocity = if controller.jumping {
+                    velocity
+                } else {
+                    velocity + controller.ground_velocity
+                };
+                (
+                    velocity - controller.velocity
+                        + Vec3::unit_y() * controller.gravity * controller.dt,
+                    velocity,
+                )
+            }
+            // Launches replace the current velocity, including that of dynamic bodies
+            None if launching => {
+                controller.jumping = true;
+                let velocity = controller.ground_velocity + external;
+                (velocity - controller.velocity, velocity)
+            }
+            None => {
+                // Surface properties of the ground being stood on
+                let (surface_velocity, friction) = ground
+                    .filter(|_| !controller.jumping)
+                    .and_then(|ground| ground.hit)
+                    .and_then(|hit| hit.entity)
+                    .and_then(|entity| surface_query.get(entity).ok())
+                    .map_or((Vec3::zero(), 1.0), |(velocity, friction)| {
+                        (
+                            velocity.map_or(Vec3::zero(), |velocity| velocity.0),
+                            friction.map_or(1.0, |friction| friction.0),
+                        )
+                    });
+                if external.y > 1E-3 {
+                    controller.jumping = true;
+                }
+                // Walk relative to the ground so that platforms carry the character
+                let ground_velocity = controller.ground_velocity + surface_velocity;
+                controller.velocity -= ground_velocity + previous_external;
+                let speeds = controller.speeds();
+                let speeds = modifiers.map_or(speeds, |modifiers| modifiers.apply(speeds));
+                let (delta_velocity, velocity) =
+                    walk(&mut *controller, look, lock_on, friction, speeds);
+                (
+                    delta_velocity + external - previous_external,
+                    velocity + ground_velocity + external,
+                )
+            }
         };
 
-        // Handle jumping
-        let was_jumping = controller.jumping;
-        desired_velocity.y = if controller.input_state.jump {
-            controller.jumping = true;
-            controller.jump_speed
-        } else {
-            0.0
-        };
+        // Charge jumps that took off in this step, whichever mode made them
+        if controller.jumped {
+            controller.jumped = false;
+            if let Some(stamina) = stamina.as_mut() {
+                let cost = stamina.jump_cost;
+                stamina.drain(cost);
+            }
+        }
 
         // Calculate impulse - the desired momentum change for the time period
-        let delta_velocity = desired_velocity - controller.velocity * xz;
         let impulse = delta_velocity * mass.mass;
         if impulse.length_squared() > 1E-6 {
             impulse_events.send(ImpulseEvent::new(&impulse));
@@ -200,14 +429,7 @@ pub fn input_to_events(
             force_events.send(ForceEvent::new(&force));
         }
 
-        controller.velocity.x = desired_velocity.x;
-        controller.velocity.z = desired_velocity.z;
-        controller.velocity.y = if was_jumping {
-            // Apply gravity for kinematic simulation
-            (-9.81f32).mul_add(controller.dt, controller.velocity.y)
-        } else {
-            desired_velocity.y
-        };
+        controller.velocity = velocity;
 
         let translation = controller.velocity * controller.dt;
         if translation.length_squared() > 1E-6 {
@@ -218,6 +440,93 @@ pub fn input_to_events(
     }
 }
 
+/// Input driven walking / flying. Returns the desired change in velocity for the dynamic
+/// backends and the velocity after the step for kinematic simulation. `friction` scales how much
+/// of the change in x/z velocity is applied in one step and `speeds` are the effective speeds
+/// after modifiers.
+fn walk(
+    controller: &mut CharacterController,
+    look: &LookDirection,
+    lock_on: Option<&LockOn>,
+    friction: f32,
+    speeds: MovementSpeeds,
+) -> (Vec3, Vec3) {
+    let xz = Vec3::new(1.0, 0.0, 1.0);
+
+    // Calculate forward / right / up vectors
+    let target_direction = lock_on.and_then(|lock_on| lock_on.target_direction);
+    let (forward, right, _up) = if controller.fly {
+        (look.forward, look.right, look.up)
+    } else if let Some(target_direction) = target_direction {
+        // Strafe relative to the lock-on target
+        (
+            target_direction,
+            target_direction.cross(Vec3::unit_y()),
+            Vec3::unit_y(),
+        )
+    } else {
+        (
+            (look.forward * xz).normalize(),
+            (look.right * xz).normalize(),
+            Vec3::unit_y(),
+        )
+    };
+
+    // Calculate the desired velocity based on input
+    let mut desired_velocity = Vec3::zero();
+    if controller.input_state.forward {
+        desired_velocity += forward;
+    }
+    if controller.input_state.backward {
+        desired_velocity -= forward;
+    }
+    if controller.input_state.right {
+        desired_velocity += right;
+    }
+    if controller.input_state.left {
+        desired_velocity -= right;
+    }
+
+    // Limit x/z velocity to walk/run speed
+    let speed = if controller.input_state.run {
+        speeds.run
+    } else {
+        speeds.walk
+    };
+    desired_velocity = if desired_velocity.length_squared() > 1E-6 {
+        desired_velocity.normalize() * speed
+    } else {
+        // No input - apply damping to the x/z of the current velocity
+        controller.velocity * 0.5 * xz
+    };
+    // Low friction surfaces only move part of the way to the desired velocity
+    let current_velocity = controller.velocity * xz;
+    desired_velocity =
+        current_velocity + (desired_velocity - current_velocity) * friction.max(0.0).min(1.0);
+
+    // Handle jumping
+    let was_jumping = controller.jumping;
+    desired_velocity.y = if controller.input_state.jump && speeds.jump > 0.0 {
+        // Only taking off from the ground counts as a jump for stamina
+        controller.jumped |= !was_jumping;
+        controller.jumping = true;
+        speeds.jump
+    } else {
+        0.0
+    };
+
+    let delta_velocity = desired_velocity - controller.velocity * xz;
+
+    let mut velocity = desired_velocity;
+    velocity.y = if was_jumping {
+        // Apply gravity for kinematic simulation
+        (-controller.gravity).mul_add(controller.dt, controller.velocity.y)
+    } else {
+        desired_velocity.y
+    };
+    (delta_velocity, velocity)
+}
+
 pub fn controller_to_yaw(
     mut reader: ResMut<ControllerEvents>,
     yaws: Res<Events<YawEvent>>,
@@ -230,14 +539,65 @@ pub fn controller_to_yaw(
     }
 }
 
+/// The only writer of the head translation: the rest translation from spawn, moved sideways by the
+/// head's `Lean` and lowered by the body's `Slide`. Runs in `CAMERA_EFFECTS`, after `lean_to_head`
+/// and `slide_head` have updated the offsets in `UPDATE`. Also places `Lean::origin` at the rest
+/// position for the obstacle checks of the physics backends.
+pub fn head_translation(
+    mut rests: Local<HashMap<Entity, Vec3>>,
+    body_query: Query<(&HeadEntity, Option<&Slide>)>,
+    mut head_query: Query<(&mut Transform, Option<&Parent>, Option<&mut Lean>), With<HeadTag>>,
+    parent_query: Query<&GlobalTransform>,
+) {
+    for (head_entity, slide) in body_query.iter() {
+        if let Ok((mut transform, parent, lean)) = head_query.get_mut(head_entity.0) {
+            if lean.is_none() && slide.is_none() {
+                continue;
+            }
+            let rest = *rests.entry(head_entity.0).or_insert(transform.translation);
+            // The head is a child of the yaw entity, so its local x axis points to the right
+            let side = match lean {
+                Some(mut lean) => {
+                    let parent_transform =
+                        parent.and_then(|parent| parent_query.get(parent.0).ok());
+                    if let Some(parent_transform) = parent_transform {
+                        lean.origin =
+                            parent_transform.translation + parent_transform.rotation * rest;
+                        lean.right = parent_transform.rotation * Vec3::unit_x();
+                    }
+                    lean.offset()
+                }
+                None => 0.0,
+            };
+            let drop = slide.map_or(0.0, |slide| slide.head_offset());
+            transform.translation = rest + Vec3::unit_x() * side - Vec3::unit_y() * drop;
+        }
+    }
+}
+
+/// The only writer of the head rotation: pitch and roll from the look events plus the lean roll.
+/// Heads with a `Lean` are updated every frame so that leaning without moving the mouse applies.
 pub fn controller_to_pitch(
+    mut pitch_roll: Local<(f32, f32)>,
     mut reader: ResMut<ControllerEvents>,
     pitches: Res<Events<PitchEvent>>,
-    mut query: Query<&mut Transform, With<HeadTag>>,
+    rolls: Res<Events<RollEvent>>,
+    mut query: Query<(&mut Transform, Option<&Lean>), With<HeadTag>>,
 ) {
-    if let Some(pitch) = reader.pitches.latest(&pitches) {
-        for mut transform in query.iter_mut() {
-            transform.rotation = Quat::from_rotation_ypr(0.0, **pitch, 0.0);
+    // NOTE: Roll events are always sent alongside pitch events
+    let changed = match reader.pitches.latest(&pitches) {
+        Some(pitch) => {
+            pitch_roll.0 = **pitch;
+            pitch_roll.1 = reader.rolls.latest(&rolls).map_or(0.0, |roll| **roll);
+            true
+        }
+        None => false,
+    };
+    for (mut transform, lean) in query.iter_mut() {
+        if !changed && lean.is_none() {
+            continue;
         }
+        let lean_roll = lean.map_or(0.0, |lean| lean.roll());
+        transform.rotation = Quat::from_rotation_ypr(0.0, pitch_roll.0, pitch_roll.1 + lean_roll);
     }
 }
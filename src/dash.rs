@@ -0,0 +1,143 @@
+// Dash / dodge ability
+use crate::{
+    controller::{CharacterController, MovementMode},
+    look::{LookDirection, LookEntity},
+    modifiers::SpeedModifiers,
+    stamina::Stamina,
+};
+use bevy::prelude::*;
+
+/// Insert on the character body. The dash key applies a burst of velocity in the input direction,
+/// or the look direction when there is no input, through `CharacterController::override_velocity`
+/// so that it produces translation, impulse and force events like regular movement.
+pub struct Dash {
+    /// Dash speed in m/s
+    pub speed: f32,
+    /// Dash duration in seconds
+    pub duration: f32,
+    /// Time in seconds from the start of a dash until the next one is allowed
+    pub cooldown: f32,
+    /// Time in seconds from the start of a dash during which `invulnerable` is set
+    pub invulnerability: f32,
+    /// Number of dashes allowed before landing again
+    pub air_dashes: u32,
+    pub dashing: bool,
+    /// Whether the character is in the invulnerability window, for gameplay code to read
+    pub invulnerable: bool,
+    pub direction: Vec3,
+    elapsed: f32,
+    cooldown_remaining: f32,
+    air_dashes_used: u32,
+}
+
+impl Default for Dash {
+    fn default() -> Self {
+        Self {
+            speed: 20.0,
+            duration: 0.2,
+            cooldown: 0.75,
+            invulnerability: 0.15,
+            air_dashes: 1,
+            dashing: false,
+            invulnerable: false,
+            direction: Vec3::zero(),
+            elapsed: 0.0,
+            cooldown_remaining: 0.0,
+            air_dashes_used: 0,
+        }
+    }
+}
+
+impl Dash {
+    pub fn can_dash(&self, grounded: bool) -> bool {
+        !self.dashing
+            && self.cooldown_remaining <= 0.0
+            && (grounded || self.air_dashes_used < self.air_dashes)
+    }
+
+    pub fn start(&mut self, direction: Vec3, grounded: bool) {
+        self.dashing = true;
+        self.invulnerable = self.invulnerability > 0.0;
+        self.direction = direction;
+        self.elapsed = 0.0;
+        self.cooldown_remaining = self.cooldown;
+        if !grounded {
+            self.air_dashes_used += 1;
+        }
+    }
+}
+
+pub fn dash(
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut query: Query<(
+        &LookEntity,
+        &mut CharacterController,
+        &mut Dash,
+        Option<&mut Stamina>,
+        Option<&SpeedModifiers>,
+    )>,
+    look_direction_query: Query<&LookDirection>,
+) {
+    let dt = time.delta_seconds();
+    let xz = Vec3::new(1.0, 0.0, 1.0);
+    for (look_entity, mut controller, mut dash, stamina, modifiers) in query.iter_mut() {
+        let grounded = !controller.jumping;
+        if grounded {
+            dash.air_dashes_used = 0;
+        }
+        dash.cooldown_remaining -= dt;
+
+        if keyboard_input.just_pressed(controller.input_map.key_dash)
+            && dash.can_dash(grounded)
+            && !modifiers.map_or(false, |modifiers| modifiers.rooted())
+            && stamina.map_or(true, |mut stamina| {
+                let cost = stamina.dash_cost;
+                stamina.try_spend(cost)
+            })
+        {
+            let look = look_direction_query
+                .get_component::<LookDirection>(look_entity.0)
+                .expect("Failed to get LookDirection from Entity");
+            let (forward, right) = if controller.fly {
+                (look.forward, look.right)
+            } else {
+                (
+                    (look.forward * xz).normalize(),
+                    (look.right * xz).normalize(),
+                )
+            };
+            let input_map = &controller.input_map;
+            let mut direction = Vec3::zero();
+            if keyboard_input.pressed(input_map.key_forward) {
+                direction += forward;
+            }
+            if keyboard_input.pressed(input_map.key_backward) {
+                direction -= forward;
+            }
+            if keyboard_input.pressed(input_map.key_right) {
+                direction += right;
+            }
+            if keyboard_input.pressed(input_map.key_left) {
+                direction -= right;
+            }
+            let direction = if direction.length_squared() > 1E-6 {
+                direction.normalize()
+            } else {
+                forward
+            };
+            dash.start(direction, grounded);
+        }
+
+        if dash.dashing {
+            dash.elapsed += dt;
+            dash.invulnerable = dash.elapsed < dash.invulnerability;
+            if dash.elapsed >= dash.duration {
+                dash.dashing = false;
+                dash.invulnerable = false;
+            } else {
+                controller.override_velocity(MovementMode::Dash, dash.direction * dash.speed);
+            }
+        }
+    }
+}
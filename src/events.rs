@@ -8,6 +8,7 @@ pub struct ControllerEvents {
     pub forces: EventReader<ForceEvent>,
     pub yaws: EventReader<YawEvent>,
     pub pitches: EventReader<PitchEvent>,
+    pub rolls: EventReader<RollEvent>,
     pub looks: EventReader<LookEvent>,
     pub look_deltas: EventReader<LookDeltaEvent>,
 }
@@ -71,6 +72,25 @@ impl Deref for PitchEvent {
     }
 }
 
+#[derive(Debug)]
+pub struct RollEvent {
+    roll: f32,
+}
+
+impl RollEvent {
+    pub fn new(value: f32) -> Self {
+        Self { roll: value }
+    }
+}
+
+impl Deref for RollEvent {
+    type Target = f32;
+
+    fn deref(&self) -> &Self::Target {
+        &self.roll
+    }
+}
+
 #[derive(Debug)]
 pub struct YawEvent {
     yaw: f32,
@@ -90,6 +110,26 @@ impl Deref for YawEvent {
     }
 }
 
+/// Adds trauma to every `CameraShake`, e.g. for explosions and hard landings
+#[derive(Debug)]
+pub struct TraumaEvent {
+    trauma: f32,
+}
+
+impl TraumaEvent {
+    pub fn new(value: f32) -> Self {
+        Self { trauma: value }
+    }
+}
+
+impl Deref for TraumaEvent {
+    type Target = f32;
+
+    fn deref(&self) -> &Self::Target {
+        &self.trauma
+    }
+}
+
 #[derive(Debug)]
 pub struct TranslationEvent {
     translation: Vec3,
@@ -148,3 +188,43 @@ impl Deref for ForceEvent {
         &self.force
     }
 }
+
+/// Sent when the `Stamina` of the character body `entity` runs out
+#[derive(Debug)]
+pub struct StaminaDepletedEvent {
+    entity: Entity,
+}
+
+impl StaminaDepletedEvent {
+    pub fn new(entity: Entity) -> Self {
+        Self { entity }
+    }
+}
+
+impl Deref for StaminaDepletedEvent {
+    type Target = Entity;
+
+    fn deref(&self) -> &Self::Target {
+        &self.entity
+    }
+}
+
+/// Sent when the `Stamina` of the character body `entity` has recovered enough to sprint again
+#[derive(Debug)]
+pub struct StaminaRecoveredEvent {
+    entity: Entity,
+}
+
+impl StaminaRecoveredEvent {
+    pub fn new(entity: Entity) -> Self {
+        Self { entity }
+    }
+}
+
+impl Deref for StaminaRecoveredEvent {
+    type Target = Entity;
+
+    fn deref(&self) -> &Self::Target {
+        &self.entity
+    }
+}
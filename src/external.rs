@@ -0,0 +1,261 @@
+// Velocity from outside the character, e.g. knockback, wind and jump pads
+use bevy::prelude::*;
+
+/// A source of external velocity that decays according to its drag
+#[derive(Clone, Copy, Debug)]
+pub struct VelocityLayer {
+    /// Identifies persistent layers so that they can be updated or removed. Layers without an id
+    /// are removed once they have decayed. Ids from `u32::MAX - 2` up are reserved for the crate,
+    /// e.g. `FORCE_FIELD_LAYER`
+    pub id: Option<u32>,
+    pub velocity: Vec3,
+    /// Change in velocity in m/s^2, integrated in each simulation step before drag
+    pub acceleration: Vec3,
+    /// Fraction of the velocity lost per second. 0 keeps it constant
+    pub drag: f32,
+    /// Remove the layer once the character is on the ground again
+    pub until_landed: bool,
+}
+
+/// Insert on the character body to push it around from gameplay code. The sum of all layers is
+/// added on top of the player driven velocity in `input_to_events`, so it applies to both the
+/// kinematic and the dynamic backends.
+pub struct ExternalVelocity {
+    /// Drag used by `apply_impulse`
+    pub impulse_drag: f32,
+    pub layers: Vec<VelocityLayer>,
+    /// Time in seconds during which movement input is ignored
+    pub control_lock: f32,
+    /// The velocity that was added in the last simulation step
+    applied: Vec3,
+    /// A launch that has not been applied in a simulation step yet
+    launching: bool,
+}
+
+impl Default for ExternalVelocity {
+    fn default() -> Self {
+        Self {
+            impulse_drag: 3.0,
+            layers: Vec::new(),
+            control_lock: 0.0,
+            applied: Vec3::zero(),
+            launching: false,
+        }
+    }
+}
+
+impl ExternalVelocity {
+    /// A one-off change in velocity in m/s, e.g. knockback from a hit or an explosion
+    pub fn apply_impulse(&mut self, velocity_change: Vec3) {
+        let drag = self.impulse_drag;
+        self.layers.push(VelocityLayer {
+            id: None,
+            velocity: velocity_change,
+            acceleration: Vec3::zero(),
+            drag,
+            until_landed: false,
+        });
+    }
+
+    /// Launch the character into the air, e.g. from a jump pad. In the next simulation step the
+    /// character's velocity is replaced by `velocity` and movement input is ignored for
+    /// `control_lock` seconds. The layer stays constant until landing while gravity acts on the
+    /// character's own velocity underneath it, integrated by `input_to_events` for kinematic
+    /// bodies and by the physics engine for dynamic ones, so the character follows a ballistic
+    /// arc.
+    pub fn launch(&mut self, velocity: Vec3, control_lock: f32) {
+        self.layers.retain(|layer| !layer.until_landed);
+        self.layers.push(VelocityLayer {
+            id: None,
+            velocity,
+            acceleration: Vec3::zero(),
+            drag: 0.0,
+            until_landed: true,
+        });
+        self.control_lock = control_lock;
+        self.launching = true;
+    }
+
+    /// Whether a launch is waiting to be applied in the next simulation step
+    pub fn launching(&self) -> bool {
+        self.launching
+    }
+
+    /// Remove layers that only last until landing. A launch that has not been applied yet is
+    /// kept, as the character is still on the ground it is being launched from
+    pub fn land(&mut self) {
+        if !self.launching {
+            self.layers.retain(|layer| !layer.until_landed);
+        }
+    }
+
+    /// Add or replace the persistent layer `id`, e.g. wind while inside an area
+    pub fn set_layer(&mut self, id: u32, velocity: Vec3, drag: f32) {
+        let layer = VelocityLayer {
+            id: Some(id),
+            velocity,
+            acceleration: Vec3::zero(),
+            drag,
+            until_landed: false,
+        };
+        match self.layers.iter_mut().find(|layer| layer.id == Some(id)) {
+            Some(existing) => *existing = layer,
+            None => self.layers.push(layer),
+        }
+    }
+
+    /// Set the acceleration and drag of the persistent layer `id`, keeping its velocity, e.g. for
+    /// force fields. The layer is added at rest if it does not exist
+    pub fn accelerate_layer(&mut self, id: u32, acceleration: Vec3, drag: f32) {
+        match self.layers.iter_mut().find(|layer| layer.id == Some(id)) {
+            Some(existing) => {
+                existing.acceleration = acceleration;
+                existing.drag = drag;
+            }
+            None => self.layers.push(VelocityLayer {
+                id: Some(id),
+                velocity: Vec3::zero(),
+                acceleration,
+                drag,
+                until_landed: false,
+            }),
+        }
+    }
+
+    pub fn remove_layer(&mut self, id: u32) {
+        self.layers.retain(|layer| layer.id != Some(id));
+    }
+
+    pub fn clear(&mut self) {
+        self.layers.clear();
+    }
+
+    /// The combined velocity of all layers
+    pub fn velocity(&self) -> Vec3 {
+        self.layers
+            .iter()
+            .fold(Vec3::zero(), |sum, layer| sum + layer.velocity)
+    }
+
+    /// Decay the layers over `dt` seconds, dropping one-off layers once they have stopped. Returns
+    /// the velocity added in the previous step and the velocity to add in this one. `apply` is
+    /// false when a movement mode overrides the velocity, in which case nothing is added and the
+    /// next step has nothing to remove
+    pub fn update(&mut self, dt: f32, apply: bool) -> (Vec3, Vec3) {
+        self.control_lock -= dt;
+        for layer in self.layers.iter_mut() {
+            layer.velocity += layer.acceleration * dt;
+            layer.velocity *= (1.0 - layer.drag * dt).max(0.0);
+        }
+        self.layers.retain(|layer| {
+            layer.id.is_some() || layer.until_landed || layer.velocity.length_squared() > 1E-4
+        });
+        let previous = self.applied;
+        self.applied = if apply { self.velocity() } else { Vec3::zero() };
+        self.launching &= !apply;
+        (previous, self.applied)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn update_returns_previous_and_current() {
+        let mut external = ExternalVelocity::default();
+        external.set_layer(1, Vec3::new(1.0, 0.0, 0.0), 0.0);
+        assert_eq!(
+            external.update(0.1, true),
+            (Vec3::zero(), Vec3::new(1.0, 0.0, 0.0))
+        );
+        external.set_layer(1, Vec3::new(2.0, 0.0, 0.0), 0.0);
+        assert_eq!(
+            external.update(0.1, true),
+            (Vec3::new(1.0, 0.0, 0.0), Vec3::new(2.0, 0.0, 0.0))
+        );
+    }
+
+    #[test]
+    fn update_without_apply_adds_nothing() {
+        let mut external = ExternalVelocity::default();
+        external.set_layer(1, Vec3::new(1.0, 0.0, 0.0), 0.0);
+        external.update(0.1, true);
+        assert_eq!(
+            external.update(0.1, false),
+            (Vec3::new(1.0, 0.0, 0.0), Vec3::zero())
+        );
+        // The layer itself is kept for when the velocity is applied again
+        assert_eq!(
+            external.update(0.1, true),
+            (Vec3::zero(), Vec3::new(1.0, 0.0, 0.0))
+        );
+    }
+
+    #[test]
+    fn impulses_decay_and_are_removed() {
+        let mut external = ExternalVelocity::default();
+        external.apply_impulse(Vec3::new(0.0, 0.0, 10.0));
+        let (_, current) = external.update(0.1, true);
+        assert!((current.z - 7.0).abs() < 1E-5);
+        for _ in 0..10 {
+            external.update(0.1, true);
+        }
+        assert!(external.layers.is_empty());
+        assert_eq!(external.velocity(), Vec3::zero());
+    }
+
+    #[test]
+    fn accelerated_layers_reach_terminal_velocity() {
+        let mut external = ExternalVelocity::default();
+        external.accelerate_layer(1, Vec3::new(2.0, 0.0, 0.0), 0.0);
+        let (_, current) = external.update(0.5, true);
+        assert!((current.x - 1.0).abs() < 1E-5);
+
+        // Accelerating again keeps the velocity gained so far
+        external.accelerate_layer(1, Vec3::new(2.0, 0.0, 0.0), 1.0);
+        for _ in 0..1000 {
+            external.update(0.01, true);
+        }
+        // acceleration / drag, less the drag applied after the last integration
+        assert!((external.velocity().x - 2.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn update_counts_down_control_lock() {
+        let mut external = ExternalVelocity::default();
+        external.control_lock = 0.25;
+        external.update(0.1, true);
+        assert!((external.control_lock - 0.15).abs() < 1E-5);
+    }
+
+    #[test]
+    fn land_only_removes_until_landed_layers() {
+        let mut external = ExternalVelocity::default();
+        external.set_layer(1, Vec3::new(1.0, 0.0, 0.0), 0.0);
+        external.apply_impulse(Vec3::new(0.0, 0.0, 1.0));
+        external.launch(Vec3::new(0.0, 5.0, 0.0), 0.0);
+        external.update(0.1, true);
+        external.land();
+        assert_eq!(external.layers.len(), 2);
+        assert!(external.layers.iter().all(|layer| !layer.until_landed));
+    }
+
+    #[test]
+    fn land_keeps_launch_until_applied() {
+        let mut external = ExternalVelocity::default();
+        external.launch(Vec3::new(0.0, 5.0, 0.0), 0.0);
+        external.land();
+        assert!(external.launching());
+        external.update(0.1, false);
+        external.land();
+        assert!(external.launching());
+        assert_eq!(
+            external.update(0.1, true),
+            (Vec3::zero(), Vec3::new(0.0, 5.0, 0.0))
+        );
+        assert!(!external.launching());
+        external.land();
+        assert!(external.layers.is_empty());
+    }
+}
@@ -0,0 +1,114 @@
+// Wind, explosions and other force fields
+use crate::{controller::Mass, external::ExternalVelocity, volume::contains_point};
+use bevy::prelude::*;
+
+/// Id of the `ExternalVelocity` layer that force fields accelerate. Reserved, like the other ids
+/// from `u32::MAX - 2` up, so gameplay code should use lower ids
+pub const FORCE_FIELD_LAYER: u32 = u32::MAX - 2;
+
+#[derive(Clone, Copy, Debug)]
+pub enum ForceFieldKind {
+    /// A constant force in N in world space, e.g. wind
+    Directional(Vec3),
+    /// A force in N away from the field's origin, or towards it when negative, falling off
+    /// linearly to zero at `radius` metres, e.g. an explosion
+    Radial { strength: f32, radius: f32 },
+    /// A force in N around the field's y axis, e.g. a whirlwind. `inward` pulls towards the axis
+    /// as a fraction of `strength`
+    Vortex { strength: f32, inward: f32 },
+}
+
+/// Insert on an entity with a `GlobalTransform` to push characters inside the box given by
+/// `half_extents` in the entity's space
+pub struct ForceField {
+    pub half_extents: Vec3,
+    pub kind: ForceFieldKind,
+}
+
+impl ForceField {
+    pub fn contains(&self, field_transform: &GlobalTransform, point: Vec3) -> bool {
+        contains_point(self.half_extents, field_transform, point)
+    }
+
+    /// Force in N at `point`
+    pub fn force(&self, field_transform: &GlobalTransform, point: Vec3) -> Vec3 {
+        let offset = point - field_transform.translation;
+        match self.kind {
+            ForceFieldKind::Directional(force) => force,
+            ForceFieldKind::Radial { strength, radius } => {
+                let distance = offset.length();
+                if distance < 1E-3 || distance > radius {
+                    return Vec3::zero();
+                }
+                offset / distance * strength * (1.0 - distance / radius)
+            }
+            ForceFieldKind::Vortex { strength, inward } => {
+                let axis = field_transform.rotation * Vec3::unit_y();
+                let radial = offset - axis * offset.dot(axis);
+                if radial.length_squared() < 1E-6 {
+                    return Vec3::zero();
+                }
+                let radial = radial.normalize();
+                (axis.cross(radial) - radial * inward) * strength
+            }
+        }
+    }
+}
+
+/// Insert on the character body alongside `ExternalVelocity` to be affected by force fields.
+/// Field forces accelerate the `FORCE_FIELD_LAYER` of `ExternalVelocity`, which is integrated with
+/// its drag in each simulation step, so they reach the dynamic backends as `ForceEvent`s /
+/// `ImpulseEvent`s and the kinematic path through `CharacterController::velocity`, like other
+/// external velocity.
+pub struct ForceFieldResponse {
+    /// Multiplier on the forces from force fields
+    pub susceptibility: f32,
+    /// Fraction of the velocity gained from force fields lost per second. This limits the speed
+    /// to acceleration / drag
+    pub drag: f32,
+    /// Current acceleration from force fields in m/s^2
+    pub acceleration: Vec3,
+}
+
+impl Default for ForceFieldResponse {
+    fn default() -> Self {
+        Self {
+            susceptibility: 1.0,
+            drag: 1.0,
+            acceleration: Vec3::zero(),
+        }
+    }
+}
+
+pub fn force_fields(
+    field_query: Query<(&ForceField, &GlobalTransform)>,
+    mut query: Query<(
+        &GlobalTransform,
+        &Mass,
+        &mut ForceFieldResponse,
+        &mut ExternalVelocity,
+    )>,
+) {
+    for (transform, mass, mut response, mut external) in query.iter_mut() {
+        let position = transform.translation;
+        let force = field_query
+            .iter()
+            .filter(|(field, field_transform)| field.contains(field_transform, position))
+            .fold(Vec3::zero(), |sum, (field, field_transform)| {
+                sum + field.force(field_transform, position)
+            });
+        response.acceleration = force * response.susceptibility / mass.mass.max(1E-3);
+
+        // Outside of all fields the layer is kept until its velocity has decayed
+        let stopped = external
+            .layers
+            .iter()
+            .find(|layer| layer.id == Some(FORCE_FIELD_LAYER))
+            .map_or(true, |layer| layer.velocity.length_squared() <= 1E-4);
+        if stopped && response.acceleration.length_squared() <= 1E-6 {
+            external.remove_layer(FORCE_FIELD_LAYER);
+        } else {
+            external.accelerate_layer(FORCE_FIELD_LAYER, response.acceleration, response.drag);
+        }
+    }
+}
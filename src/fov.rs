@@ -0,0 +1,82 @@
+// Field of view kick with speed and aim / zoom
+use crate::{
+    controller::CharacterController,
+    look::{LookEntity, MouseSettings},
+};
+use bevy::{
+    prelude::*,
+    render::camera::{Camera, CameraProjection, PerspectiveProjection},
+};
+
+/// Insert on the character body to drive the perspective field of view of its look camera.
+pub struct FovKick {
+    /// Field of view in radians at or below walk speed
+    pub base_fov: f32,
+    /// Field of view in radians at run speed
+    pub run_fov: f32,
+    /// Field of view in radians while aiming
+    pub aim_fov: f32,
+    /// Rate at which the field of view follows its target, per second
+    pub smoothing: f32,
+    /// Scale look sensitivity in proportion to the zoom when the field of view is narrowed
+    pub scale_sensitivity: bool,
+    /// Whether the aim action is held
+    pub aiming: bool,
+    /// Current field of view in radians
+    pub fov: f32,
+}
+
+impl Default for FovKick {
+    fn default() -> Self {
+        let base_fov = std::f32::consts::FRAC_PI_4;
+        Self {
+            base_fov,
+            run_fov: 1.15 * base_fov,
+            aim_fov: 0.5 * base_fov,
+            smoothing: 8.0,
+            scale_sensitivity: true,
+            aiming: false,
+            fov: base_fov,
+        }
+    }
+}
+
+impl FovKick {
+    fn target_fov(&self, controller: &CharacterController) -> f32 {
+        if self.aiming {
+            return self.aim_fov;
+        }
+        let speed = (controller.velocity * Vec3::new(1.0, 0.0, 1.0)).length();
+        let t = (speed - controller.walk_speed)
+            / (controller.run_speed - controller.walk_speed).max(1E-3);
+        self.base_fov + (self.run_fov - self.base_fov) * t.max(0.0).min(1.0)
+    }
+}
+
+pub fn fov_kick(
+    time: Res<Time>,
+    mouse_button_input: Res<Input<MouseButton>>,
+    mut settings: ResMut<MouseSettings>,
+    mut query: Query<(&CharacterController, &LookEntity, &mut FovKick)>,
+    mut camera_query: Query<(&mut Camera, &mut PerspectiveProjection)>,
+) {
+    for (controller, look_entity, mut kick) in query.iter_mut() {
+        kick.aiming = mouse_button_input.pressed(controller.input_map.button_aim);
+        let target = kick.target_fov(controller);
+        let step = (kick.smoothing * time.delta_seconds()).min(1.0);
+        kick.fov += (target - kick.fov) * step;
+
+        if kick.scale_sensitivity {
+            // Match the apparent on-screen motion to the zoom
+            settings.sensitivity_scale =
+                (0.5 * kick.fov.min(kick.base_fov)).tan() / (0.5 * kick.base_fov).tan();
+        }
+
+        if let Ok((mut camera, mut projection)) = camera_query.get_mut(look_entity.0) {
+            if (projection.fov - kick.fov).abs() > 1E-6 {
+                projection.fov = kick.fov;
+                camera.projection_matrix = projection.get_projection_matrix();
+            }
+        }
+    }
+}
@@ -0,0 +1,125 @@
+// Procedural head bob, landing dip and idle sway applied to the camera
+use crate::{camera::CameraOffset, controller::CharacterController, look::LookEntity};
+use bevy::prelude::*;
+
+/// Insert on the character body. Set `enabled` to false, or any amplitude to 0, to turn the
+/// effects off for motion sickness accessibility.
+pub struct HeadBob {
+    pub enabled: bool,
+    /// Vertical bob amplitude in metres at walk speed
+    pub walk_amplitude: f32,
+    /// Vertical bob amplitude in metres at run speed
+    pub run_amplitude: f32,
+    /// Sideways bob amplitude relative to the vertical amplitude
+    pub sideways_ratio: f32,
+    /// Distance in metres travelled per bob cycle
+    pub stride: f32,
+    /// Downward offset in metres per m/s of fall speed on landing
+    pub landing_dip: f32,
+    /// Maximum landing dip in metres
+    pub landing_dip_max: f32,
+    /// Rate at which the landing dip recovers, per second
+    pub landing_recovery: f32,
+    /// Idle sway amplitude in radians
+    pub idle_sway: f32,
+    /// Idle sway frequency in Hz
+    pub idle_frequency: f32,
+    /// Rate at which the bob amplitude follows speed changes, per second
+    pub smoothing: f32,
+    phase: f32,
+    amplitude: f32,
+    dip: f32,
+    fall_speed: f32,
+    was_grounded: bool,
+    time: f32,
+}
+
+impl Default for HeadBob {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            walk_amplitude: 0.03,
+            run_amplitude: 0.06,
+            sideways_ratio: 0.5,
+            stride: 1.6,
+            landing_dip: 0.02,
+            landing_dip_max: 0.2,
+            landing_recovery: 8.0,
+            idle_sway: 0.003,
+            idle_frequency: 0.25,
+            smoothing: 10.0,
+            phase: 0.0,
+            amplitude: 0.0,
+            dip: 0.0,
+            fall_speed: 0.0,
+            was_grounded: true,
+            time: 0.0,
+        }
+    }
+}
+
+impl HeadBob {
+    fn target_amplitude(&self, controller: &CharacterController, speed: f32) -> f32 {
+        if speed <= controller.walk_speed {
+            self.walk_amplitude * speed / controller.walk_speed.max(1E-3)
+        } else {
+            let t = (speed - controller.walk_speed)
+                / (controller.run_speed - controller.walk_speed).max(1E-3);
+            self.walk_amplitude + (self.run_amplitude - self.walk_amplitude) * t.min(1.0)
+        }
+    }
+}
+
+pub fn head_bob(
+    time: Res<Time>,
+    mut query: Query<(&CharacterController, &LookEntity, &mut HeadBob)>,
+    mut camera_query: Query<&mut CameraOffset>,
+) {
+    let dt = time.delta_seconds();
+    for (controller, look_entity, mut bob) in query.iter_mut() {
+        if !bob.enabled {
+            continue;
+        }
+        let grounded = !controller.jumping;
+        let speed = (controller.velocity * Vec3::new(1.0, 0.0, 1.0)).length();
+
+        // Walk / run bob
+        let target = if grounded {
+            bob.target_amplitude(controller, speed)
+        } else {
+            0.0
+        };
+        bob.amplitude += (target - bob.amplitude) * (bob.smoothing * dt).min(1.0);
+        if grounded {
+            bob.phase = (bob.phase + std::f32::consts::TAU * speed * dt / bob.stride)
+                % std::f32::consts::TAU;
+        }
+        let vertical = bob.amplitude * (2.0 * bob.phase).sin();
+        let sideways = bob.amplitude * bob.sideways_ratio * bob.phase.sin();
+
+        // Landing dip
+        if grounded {
+            if !bob.was_grounded {
+                bob.dip += (bob.fall_speed * bob.landing_dip).min(bob.landing_dip_max);
+                bob.dip = bob.dip.min(bob.landing_dip_max);
+            }
+            bob.fall_speed = 0.0;
+        } else {
+            bob.fall_speed = bob.fall_speed.max(-controller.velocity.y);
+        }
+        bob.was_grounded = grounded;
+        bob.dip -= bob.dip * (bob.landing_recovery * dt).min(1.0);
+
+        // Idle sway fades out as the character starts moving
+        bob.time += dt;
+        let idle = (1.0 - speed / controller.walk_speed.max(1E-3)).max(0.0);
+        let sway = bob.idle_sway * idle;
+        let angle = std::f32::consts::TAU * bob.idle_frequency * bob.time;
+        let rotation =
+            Quat::from_rotation_ypr(0.5 * sway * (0.5 * angle).sin(), sway * angle.sin(), 0.0);
+
+        if let Ok(mut offset) = camera_query.get_mut(look_entity.0) {
+            offset.add(Vec3::new(sideways, vertical - bob.dip, 0.0), rotation);
+        }
+    }
+}
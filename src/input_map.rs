@@ -1,4 +1,4 @@
-use bevy::input::keyboard::KeyCode;
+use bevy::input::{keyboard::KeyCode, mouse::MouseButton};
 
 pub struct InputMap {
     pub key_forward: KeyCode,
@@ -8,6 +8,16 @@ pub struct InputMap {
     pub key_jump: KeyCode,
     pub key_run: KeyCode,
     pub key_crouch: KeyCode,
+    pub key_roll_left: KeyCode,
+    pub key_roll_right: KeyCode,
+    pub key_lean_left: KeyCode,
+    pub key_lean_right: KeyCode,
+    pub button_aim: MouseButton,
+    pub key_toggle_view: KeyCode,
+    pub key_swap_shoulder: KeyCode,
+    pub key_lock_on: KeyCode,
+    pub key_cycle_target: KeyCode,
+    pub key_dash: KeyCode,
     pub invert_y: bool,
 }
 
@@ -21,6 +31,16 @@ impl Default for InputMap {
             key_jump: KeyCode::Space,
             key_run: KeyCode::LShift,
             key_crouch: KeyCode::LControl,
+            key_roll_left: KeyCode::Z,
+            key_roll_right: KeyCode::X,
+            key_lean_left: KeyCode::Q,
+            key_lean_right: KeyCode::E,
+            button_aim: MouseButton::Right,
+            key_toggle_view: KeyCode::V,
+            key_swap_shoulder: KeyCode::C,
+            key_lock_on: KeyCode::T,
+            key_cycle_target: KeyCode::Tab,
+            key_dash: KeyCode::LAlt,
             invert_y: false,
         }
     }
@@ -0,0 +1,150 @@
+// Jump pads and launchers
+use crate::{controller::CharacterController, external::ExternalVelocity, volume::contains_point};
+use bevy::{prelude::*, utils::HashSet};
+
+#[derive(Clone, Copy, Debug)]
+pub enum Launch {
+    /// Launch with a fixed velocity in m/s
+    Velocity(Vec3),
+    /// Launch towards a world space point, peaking `apex_height` metres above the higher of the
+    /// start and the target
+    Target { point: Vec3, apex_height: f32 },
+}
+
+/// Insert on an entity with a `GlobalTransform` to launch characters that enter the box given by
+/// `half_extents` in the entity's space. Characters need `ExternalVelocity` to be launched.
+pub struct JumpPad {
+    pub half_extents: Vec3,
+    pub launch: Launch,
+    /// Time in seconds after launching during which movement input is ignored
+    pub air_control_lock: f32,
+}
+
+impl JumpPad {
+    pub fn contains(&self, pad_transform: &GlobalTransform, point: Vec3) -> bool {
+        contains_point(self.half_extents, pad_transform, point)
+    }
+
+    /// Velocity to launch a character at `from` with, under `gravity` in m/s^2
+    pub fn launch_velocity(&self, from: Vec3, gravity: f32) -> Vec3 {
+        match self.launch {
+            Launch::Velocity(velocity) => velocity,
+            Launch::Target { point, apex_height } => {
+                let gravity = gravity.max(1E-3);
+                let apex = from.y.max(point.y) + apex_height.max(0.0);
+                let up_speed = (2.0 * gravity * (apex - from.y)).sqrt();
+                let time = up_speed / gravity + (2.0 * (apex - point.y) / gravity).sqrt();
+                let mut velocity = (point - from) / time.max(1E-3);
+                velocity.y = up_speed;
+                velocity
+            }
+        }
+    }
+}
+
+/// Pads and characters that were overlapping in the last frame, so that a launch only happens on
+/// entering a pad
+#[derive(Default)]
+pub struct JumpPadState {
+    occupied: HashSet<(Entity, Entity)>,
+}
+
+pub fn jump_pads(
+    mut state: Local<JumpPadState>,
+    pad_query: Query<(Entity, &JumpPad, &GlobalTransform)>,
+    mut query: Query<(
+        Entity,
+        &GlobalTransform,
+        &CharacterController,
+        &mut ExternalVelocity,
+    )>,
+) {
+    let mut occupied = HashSet::default();
+    for (pad_entity, pad, pad_transform) in pad_query.iter() {
+        for (entity, transform, controller, mut external) in query.iter_mut() {
+            let position = transform.translation;
+            if !pad.contains(pad_transform, position) {
+                continue;
+            }
+            occupied.insert((pad_entity, entity));
+            if state.occupied.contains(&(pad_entity, entity)) {
+                continue;
+            }
+            let velocity = pad.launch_velocity(position, controller.gravity);
+            external.launch(velocity, pad.air_control_lock);
+        }
+    }
+    state.occupied = occupied;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pad(launch: Launch) -> JumpPad {
+        JumpPad {
+            half_extents: Vec3::one(),
+            launch,
+            air_control_lock: 0.0,
+        }
+    }
+
+    #[test]
+    fn fixed_velocity_is_unchanged() {
+        let velocity = Vec3::new(1.0, 10.0, -2.0);
+        let pad = pad(Launch::Velocity(velocity));
+        assert_eq!(
+            pad.launch_velocity(Vec3::new(5.0, 1.0, 3.0), 9.81),
+            velocity
+        );
+    }
+
+    fn check_target(from: Vec3, point: Vec3, apex_height: f32) {
+        let gravity = 9.81;
+        let velocity = pad(Launch::Target { point, apex_height }).launch_velocity(from, gravity);
+
+        // Peaks apex_height above the higher end
+        let apex = from.y + velocity.y * velocity.y / (2.0 * gravity);
+        assert!((apex - (from.y.max(point.y) + apex_height)).abs() < 1E-3);
+
+        // Lands on the target on the way down
+        let horizontal = Vec3::new(point.x - from.x, 0.0, point.z - from.z);
+        let horizontal_speed = Vec3::new(velocity.x, 0.0, velocity.z).length();
+        let time = horizontal.length() / horizontal_speed;
+        let height = from.y + velocity.y * time - 0.5 * gravity * time * time;
+        assert!((height - point.y).abs() < 1E-3);
+        assert!(velocity.y - gravity * time <= 0.0);
+        assert!(horizontal.normalize().dot(velocity.normalize()) > 0.0);
+    }
+
+    #[test]
+    fn target_on_level_ground() {
+        check_target(Vec3::zero(), Vec3::new(10.0, 0.0, 0.0), 3.0);
+    }
+
+    #[test]
+    fn target_above() {
+        check_target(Vec3::new(0.0, 1.0, 0.0), Vec3::new(4.0, 6.0, -8.0), 1.0);
+    }
+
+    #[test]
+    fn target_below() {
+        check_target(Vec3::new(0.0, 5.0, 0.0), Vec3::new(-6.0, 0.0, 2.0), 2.0);
+    }
+
+    #[test]
+    fn contains_uses_pad_rotation() {
+        let pad = JumpPad {
+            half_extents: Vec3::new(2.0, 0.5, 0.5),
+            launch: Launch::Velocity(Vec3::zero()),
+            air_control_lock: 0.0,
+        };
+        let transform = GlobalTransform {
+            translation: Vec3::new(0.0, 1.0, 0.0),
+            rotation: Quat::from_rotation_y(std::f32::consts::FRAC_PI_2),
+            scale: Vec3::one(),
+        };
+        assert!(pad.contains(&transform, Vec3::new(0.0, 1.0, 1.5)));
+        assert!(!pad.contains(&transform, Vec3::new(1.5, 1.0, 0.0)));
+    }
+}
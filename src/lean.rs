@@ -0,0 +1,94 @@
+// Lean / peek around corners by offsetting and rolling the head
+use crate::controller::{CharacterController, HeadEntity};
+use bevy::prelude::*;
+
+/// Insert on the `HeadTag` entity. The physics backends limit the lean through
+/// `clearance_left` / `clearance_right` so that the head cannot lean through walls.
+pub struct Lean {
+    /// Sideways offset of the head in metres at full lean
+    pub max_offset: f32,
+    /// Roll of the head in radians at full lean
+    pub max_angle: f32,
+    /// Rate of change of `amount` per second
+    pub speed: f32,
+    /// Radius kept clear around the head when checking for obstacles
+    pub radius: f32,
+    /// Desired lean in [-1, 1], negative is left. Set from input, or directly for remote characters
+    pub target: f32,
+    /// Current lean in [-1, 1], negative is left. Read this for networking and animation
+    pub amount: f32,
+    /// Fraction of a full lean to the left that is free of obstacles, in [0, 1]
+    pub clearance_left: f32,
+    /// Fraction of a full lean to the right that is free of obstacles, in [0, 1]
+    pub clearance_right: f32,
+    /// World space position of the head when not leaning, for obstacle checks. Set by
+    /// `head_translation`, which keeps the rest translation of the head
+    pub origin: Vec3,
+    /// World space direction of a lean to the right, for obstacle checks
+    pub right: Vec3,
+}
+
+impl Default for Lean {
+    fn default() -> Self {
+        Self {
+            max_offset: 0.4,
+            max_angle: 0.3,
+            speed: 4.0,
+            radius: 0.2,
+            target: 0.0,
+            amount: 0.0,
+            clearance_left: 1.0,
+            clearance_right: 1.0,
+            origin: Vec3::zero(),
+            right: Vec3::unit_x(),
+        }
+    }
+}
+
+impl Lean {
+    /// Roll of the head due to leaning, in radians
+    pub fn roll(&self) -> f32 {
+        -self.amount * self.max_angle
+    }
+
+    /// Sideways offset of the head due to leaning, in metres
+    pub fn offset(&self) -> f32 {
+        self.amount * self.max_offset
+    }
+}
+
+pub fn input_to_lean(
+    keyboard_input: Res<Input<KeyCode>>,
+    controller_query: Query<(&CharacterController, &HeadEntity)>,
+    mut lean_query: Query<&mut Lean>,
+) {
+    for (controller, head_entity) in controller_query.iter() {
+        if let Ok(mut lean) = lean_query.get_mut(head_entity.0) {
+            let mut target = 0.0;
+            if keyboard_input.pressed(controller.input_map.key_lean_left) {
+                target -= 1.0;
+            }
+            if keyboard_input.pressed(controller.input_map.key_lean_right) {
+                target += 1.0;
+            }
+            lean.target = target;
+        }
+    }
+}
+
+/// Updates the lean amount towards its target. The lean offset is applied to the head translation
+/// by `head_translation` and the lean roll with the pitch by `controller_to_pitch`.
+pub fn lean_to_head(time: Res<Time>, mut head_query: Query<&mut Lean>) {
+    for mut lean in head_query.iter_mut() {
+        let target = lean
+            .target
+            .max(-lean.clearance_left)
+            .min(lean.clearance_right);
+        let step = lean.speed * time.delta_seconds();
+        lean.amount = if (target - lean.amount).abs() <= step {
+            target
+        } else {
+            lean.amount + step * (target - lean.amount).signum()
+        };
+    }
+}
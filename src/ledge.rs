@@ -0,0 +1,209 @@
+// Ledge grab, hang, shimmy and mantle
+use crate::{
+    controller::{CharacterController, MovementMode},
+    look::{LookDirection, LookEntity},
+    probe::RayHit,
+    stamina::Stamina,
+};
+use bevy::prelude::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LedgeState {
+    None,
+    Hanging,
+    Mantling,
+}
+
+/// Insert on the character body. While airborne, a ledge found in front of the character at head
+/// height by the physics backends is grabbed. While hanging, left / right shimmy along the ledge,
+/// jump mantles up onto it and crouch lets go.
+pub struct LedgeGrab {
+    /// Height above the body origin of the forward cast that looks for a wall
+    pub grab_height: f32,
+    /// Length of the forward cast
+    pub reach: f32,
+    /// Distance above `grab_height` to search for the top of the ledge
+    pub grab_range: f32,
+    /// Horizontal distance in metres from the ledge to the body origin while hanging
+    pub hang_distance: f32,
+    /// Vertical distance in metres from the ledge down to the body origin while hanging
+    pub hang_drop: f32,
+    /// Shimmy speed in m/s
+    pub shimmy_speed: f32,
+    /// Time in seconds to mantle up onto the ledge
+    pub mantle_duration: f32,
+    /// Height of the body origin above the ledge after mantling
+    pub stand_height: f32,
+    /// Distance in metres onto the ledge to move when mantling
+    pub mantle_forward: f32,
+    /// Time in seconds after letting go before a ledge can be grabbed again
+    pub regrab_delay: f32,
+    /// Wall in front of the character, written by the physics backends
+    pub wall: Option<RayHit>,
+    /// Top of the ledge, written by the physics backends
+    pub top: Option<RayHit>,
+    /// Horizontal direction to look for ledges in, for the physics backends
+    pub forward: Vec3,
+    /// Current state, e.g. for animation
+    pub state: LedgeState,
+    pub ledge_point: Vec3,
+    pub ledge_normal: Vec3,
+    mantle_from: Vec3,
+    mantle_to: Vec3,
+    elapsed: f32,
+    regrab_remaining: f32,
+}
+
+impl Default for LedgeGrab {
+    fn default() -> Self {
+        Self {
+            grab_height: 0.8,
+            reach: 0.7,
+            grab_range: 0.5,
+            hang_distance: 0.4,
+            hang_drop: 1.0,
+            shimmy_speed: 1.5,
+            mantle_duration: 0.6,
+            stand_height: 1.2,
+            mantle_forward: 0.5,
+            regrab_delay: 0.5,
+            wall: None,
+            top: None,
+            forward: -Vec3::unit_z(),
+            state: LedgeState::None,
+            ledge_point: Vec3::zero(),
+            ledge_normal: Vec3::zero(),
+            mantle_from: Vec3::zero(),
+            mantle_to: Vec3::zero(),
+            elapsed: 0.0,
+            regrab_remaining: 0.0,
+        }
+    }
+}
+
+impl LedgeGrab {
+    /// The wall and ledge top if they form a grabbable ledge
+    fn ledge(&self) -> Option<(RayHit, RayHit)> {
+        match (self.wall, self.top) {
+            (Some(wall), Some(top))
+                if wall.normal.y.abs() < 0.3 && top.normal.y > 0.7 && top.distance > 1E-3 =>
+            {
+                Some((wall, top))
+            }
+            _ => None,
+        }
+    }
+
+    fn hang_position(&self) -> Vec3 {
+        self.ledge_point + self.ledge_normal * self.hang_distance - Vec3::unit_y() * self.hang_drop
+    }
+
+    /// Position along the mantle path, rising first and then moving onto the ledge
+    fn mantle_position(&self, t: f32) -> Vec3 {
+        let smoothstep = |t: f32| {
+            let t = t.max(0.0).min(1.0);
+            t * t * (3.0 - 2.0 * t)
+        };
+        let up = smoothstep(t / 0.6);
+        let across = smoothstep((t - 0.4) / 0.6);
+        let delta = self.mantle_to - self.mantle_from;
+        self.mantle_from + Vec3::new(delta.x * across, delta.y * up, delta.z * across)
+    }
+}
+
+pub fn ledge_grab(
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut query: Query<(
+        &LookEntity,
+        &GlobalTransform,
+        &mut CharacterController,
+        &mut LedgeGrab,
+        Option<&Stamina>,
+    )>,
+    look_direction_query: Query<&LookDirection>,
+) {
+    let dt = time.delta_seconds();
+    let xz = Vec3::new(1.0, 0.0, 1.0);
+    for (look_entity, transform, mut controller, mut ledge, stamina) in query.iter_mut() {
+        let position = transform.translation;
+        ledge.regrab_remaining -= dt;
+
+        match ledge.state {
+            LedgeState::None => {
+                let look = look_direction_query
+                    .get_component::<LookDirection>(look_entity.0)
+                    .expect("Failed to get LookDirection from Entity");
+                ledge.forward = (look.forward * xz).normalize();
+
+                if !controller.jumping || ledge.regrab_remaining > 0.0 {
+                    continue;
+                }
+                if let Some((wall, top)) = ledge.ledge() {
+                    ledge.state = LedgeState::Hanging;
+                    ledge.ledge_point = top.point;
+                    ledge.ledge_normal = (wall.normal * xz).normalize();
+                }
+            }
+            LedgeState::Hanging => {
+                ledge.forward = -ledge.ledge_normal;
+                let input_map = &controller.input_map;
+                let jump = keyboard_input.just_pressed(input_map.key_jump)
+                    && stamina.map_or(true, |stamina| stamina.can_jump());
+                let drop = keyboard_input.just_pressed(input_map.key_crouch);
+                let mut shimmy = 0.0;
+                if keyboard_input.pressed(input_map.key_left) {
+                    shimmy -= 1.0;
+                }
+                if keyboard_input.pressed(input_map.key_right) {
+                    shimmy += 1.0;
+                }
+
+                match ledge.ledge() {
+                    Some((_, top)) => ledge.ledge_point.y = top.point.y,
+                    None => {
+                        // Shimmied off the end of the ledge
+                        ledge.state = LedgeState::None;
+                        ledge.regrab_remaining = ledge.regrab_delay;
+                        continue;
+                    }
+                }
+
+                if drop {
+                    ledge.state = LedgeState::None;
+                    ledge.regrab_remaining = ledge.regrab_delay;
+                    continue;
+                }
+                if jump {
+                    controller.jumped = true;
+                    ledge.state = LedgeState::Mantling;
+                    ledge.elapsed = 0.0;
+                    ledge.mantle_from = position;
+                    ledge.mantle_to = ledge.ledge_point - ledge.ledge_normal * ledge.mantle_forward
+                        + Vec3::unit_y() * ledge.stand_height;
+                    continue;
+                }
+
+                // Freeze in the hang position, moving only along the ledge
+                let along = Vec3::unit_y().cross(ledge.ledge_normal);
+                ledge.ledge_point += along * shimmy * ledge.shimmy_speed * dt;
+                let correction = (ledge.hang_position() - position) / controller.dt;
+                controller.override_velocity(MovementMode::LedgeGrab, correction);
+            }
+            LedgeState::Mantling => {
+                ledge.elapsed += dt;
+                let t = ledge.elapsed / ledge.mantle_duration.max(1E-3);
+                if t >= 1.0 {
+                    ledge.state = LedgeState::None;
+                    controller.jumping = false;
+                    controller.set_velocity_once(MovementMode::LedgeGrab, Vec3::zero());
+                    continue;
+                }
+                // Root motion style: move exactly to the next point on the mantle path
+                let next = ledge.mantle_position(t + controller.dt / ledge.mantle_duration);
+                let velocity = (next - position) / controller.dt;
+                controller.override_velocity(MovementMode::LedgeGrab, velocity);
+            }
+        }
+    }
+}
@@ -1,8 +1,32 @@
+pub mod air_movement;
+pub mod camera;
+pub mod camera_rig;
+pub mod climb;
 pub mod controller;
+pub mod dash;
 pub mod events;
+pub mod external;
+pub mod force_field;
+pub mod fov;
+pub mod head_bob;
 pub mod input_map;
+pub mod jump_pad;
+pub mod lean;
+pub mod ledge;
+pub mod lock_on;
 pub mod look;
+pub mod modifiers;
 #[cfg(feature = "use_physx")]
 pub mod physx;
+pub mod platform;
+pub mod probe;
 #[cfg(feature = "use_rapier")]
 pub mod rapier;
+pub mod shake;
+pub mod slide;
+pub mod stamina;
+pub mod surface;
+pub mod swim;
+pub mod vault;
+pub mod volume;
+pub mod wall_run;
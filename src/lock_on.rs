@@ -0,0 +1,184 @@
+// Lock-on targeting that drives the look orientation towards a target
+use crate::{
+    controller::CharacterController,
+    look::{LookDirection, LookEntity, LookOverride},
+};
+use bevy::prelude::*;
+
+/// `LookOverride::source` of the overrides inserted by lock-on. Reserved, like the other ids from
+/// `u32::MAX - 2` up, so gameplay code should use lower ids
+pub const LOCK_ON_SOURCE: u32 = u32::MAX - 1;
+
+/// Tag entities that can be locked on to
+pub struct Targetable;
+
+/// Insert on the character body. Press the lock on key to lock on to the candidate closest to
+/// the look direction and the cycle target key to move to the next candidate in the cone. While
+/// locked, yaw and pitch are driven through a `LookOverride` and movement is relative to the
+/// direction of the target. Overrides from other sources, e.g. cutscenes, take precedence.
+pub struct LockOn {
+    pub target: Option<Entity>,
+    /// Maximum distance in metres to acquire a target
+    pub max_distance: f32,
+    /// Distance in metres beyond which the lock is broken
+    pub break_distance: f32,
+    /// Half angle in radians of the cone around the look direction in which targets are acquired
+    pub cone_angle: f32,
+    /// Fraction of the way from the character to the target to look at, to frame both
+    pub framing: f32,
+    /// Added to the pitch towards the framing point, in radians
+    pub pitch_offset: f32,
+    /// Horizontal direction from the character to the target while locked
+    pub target_direction: Option<Vec3>,
+}
+
+impl Default for LockOn {
+    fn default() -> Self {
+        Self {
+            target: None,
+            max_distance: 20.0,
+            break_distance: 30.0,
+            cone_angle: std::f32::consts::FRAC_PI_4,
+            framing: 0.5,
+            pitch_offset: -0.2,
+            target_direction: None,
+        }
+    }
+}
+
+/// Signed yaw angle of `direction` relative to `forward` about the y axis
+fn relative_yaw(forward: Vec3, direction: Vec3) -> f32 {
+    let cross = forward.cross(direction);
+    cross.y.atan2(forward.dot(direction))
+}
+
+fn candidates(
+    lock_on: &LockOn,
+    position: Vec3,
+    forward: Vec3,
+    target_query: &Query<(Entity, &GlobalTransform), With<Targetable>>,
+) -> Vec<(Entity, f32)> {
+    let mut candidates = target_query
+        .iter()
+        .filter_map(|(entity, transform)| {
+            let offset = transform.translation - position;
+            let distance = offset.length();
+            if distance < 1E-3 || distance > lock_on.max_distance {
+                return None;
+            }
+            let direction = offset / distance;
+            if forward.dot(direction).max(-1.0).min(1.0).acos() > lock_on.cone_angle {
+                return None;
+            }
+            let xz = Vec3::new(1.0, 0.0, 1.0);
+            Some((entity, relative_yaw(forward * xz, direction * xz)))
+        })
+        .collect::<Vec<_>>();
+    candidates.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+    candidates
+}
+
+pub fn lock_on(
+    commands: &mut Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut controller_query: Query<(
+        Entity,
+        &CharacterController,
+        &LookEntity,
+        &GlobalTransform,
+        &mut LockOn,
+        Option<&mut LookOverride>,
+    )>,
+    look_direction_query: Query<&LookDirection>,
+    target_query: Query<(Entity, &GlobalTransform), With<Targetable>>,
+) {
+    for (entity, controller, look_entity, transform, mut lock_on, look_override) in
+        controller_query.iter_mut()
+    {
+        let look = look_direction_query
+            .get_component::<LookDirection>(look_entity.0)
+            .expect("Failed to get LookDirection from Entity");
+        let position = transform.translation;
+
+        if keyboard_input.just_pressed(controller.input_map.key_lock_on) {
+            lock_on.target = if lock_on.target.is_some() {
+                None
+            } else {
+                candidates(&lock_on, position, look.forward, &target_query)
+                    .into_iter()
+                    .min_by(|a, b| {
+                        a.1.abs()
+                            .partial_cmp(&b.1.abs())
+                            .unwrap_or(std::cmp::Ordering::Equal)
+                    })
+                    .map(|(candidate, _)| candidate)
+            };
+        } else if lock_on.target.is_some()
+            && keyboard_input.just_pressed(controller.input_map.key_cycle_target)
+        {
+            let candidates = candidates(&lock_on, position, look.forward, &target_query);
+            let current = candidates
+                .iter()
+                .position(|(candidate, _)| Some(*candidate) == lock_on.target);
+            let next = match current {
+                Some(index) => (index + 1) % candidates.len(),
+                None => 0,
+            };
+            if let Some((candidate, _)) = candidates.get(next) {
+                lock_on.target = Some(*candidate);
+            }
+        }
+
+        // Break the lock if the target has gone or is too far away
+        let target_position = lock_on
+            .target
+            .and_then(|target| target_query.get(target).ok())
+            .map(|(_, target_transform)| target_transform.translation)
+            .filter(|target_position| {
+                (*target_position - position).length() <= lock_on.break_distance
+            });
+        if target_position.is_none() {
+            lock_on.target = None;
+        }
+
+        match target_position {
+            Some(target_position) => {
+                let offset = target_position - position;
+                let horizontal = Vec3::new(offset.x, 0.0, offset.z);
+                lock_on.target_direction = if horizontal.length_squared() > 1E-6 {
+                    Some(horizontal.normalize())
+                } else {
+                    None
+                };
+
+                let focus = lock_on.framing * offset;
+                let yaw = (-offset.x).atan2(-offset.z);
+                let pitch = focus.y.atan2(focus.x.hypot(focus.z)) + lock_on.pitch_offset;
+                let yaw_pitch_roll = Vec3::new(yaw, pitch, 0.0);
+                match look_override {
+                    Some(mut look_override) if !look_override.is_releasing() => {
+                        // Leave overrides from other sources in control
+                        if look_override.source == Some(LOCK_ON_SOURCE) {
+                            look_override.yaw_pitch_roll = yaw_pitch_roll;
+                        }
+                    }
+                    _ => {
+                        commands.insert_one(
+                            entity,
+                            LookOverride::new(yaw_pitch_roll).with_source(LOCK_ON_SOURCE),
+                        );
+                    }
+                }
+            }
+            None => {
+                lock_on.target_direction = None;
+                // Only release overrides that lock-on created
+                if let Some(mut look_override) = look_override {
+                    if look_override.source == Some(LOCK_ON_SOURCE) {
+                        look_override.release();
+                    }
+                }
+            }
+        }
+    }
+}
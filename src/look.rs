@@ -1,5 +1,8 @@
 // system that converts delta axis events into pitch and yaw
-use crate::events::{LookDeltaEvent, LookEvent, PitchEvent, YawEvent};
+use crate::{
+    controller::CharacterController,
+    events::{LookDeltaEvent, LookEvent, PitchEvent, RollEvent, YawEvent},
+};
 use bevy::{input::mouse::MouseMotion, prelude::*};
 
 #[derive(Clone, Copy)]
@@ -36,6 +39,8 @@ pub fn forward_up(settings: Res<MouseSettings>, mut query: Query<&mut LookDirect
 
 pub struct MouseSettings {
     pub sensitivity: f32,
+    /// Multiplier on `sensitivity`, e.g. reduced while zoomed in
+    pub sensitivity_scale: f32,
     pub yaw_pitch_roll: Vec3,
 }
 
@@ -43,6 +48,7 @@ impl Default for MouseSettings {
     fn default() -> Self {
         Self {
             sensitivity: 0.01,
+            sensitivity_scale: 1.0,
             yaw_pitch_roll: Vec3::zero(),
         }
     }
@@ -53,34 +59,475 @@ pub struct MouseMotionState {
     event_reader: EventReader<MouseMotion>,
 }
 
+/// Takes absolute control of the look orientation for a while, e.g. for cutscenes, ladders or
+/// being grabbed. Insert it on the character body; mouse look is ignored while it is present and
+/// the component removes itself once control has been handed back to the player. The orientation
+/// stays within the `LookLimits` of the controllers.
+pub struct LookOverride {
+    /// Target yaw, pitch and roll in radians
+    pub yaw_pitch_roll: Vec3,
+    /// Time in seconds after which the override releases itself. None holds until `release()`
+    pub duration: Option<f32>,
+    /// Time in seconds to blend from the current look orientation to the target
+    pub blend_in: f32,
+    /// Time in seconds to blend back to the original orientation when `restore` is set
+    pub blend_out: f32,
+    /// Return to the orientation from before the override when released
+    pub restore: bool,
+    /// Identifies what inserted the override, so that systems such as lock-on only update their
+    /// own. None for overrides from gameplay code. Ids from `u32::MAX - 2` up are reserved for the
+    /// crate, e.g. `LOCK_ON_SOURCE`
+    pub source: Option<u32>,
+    released: bool,
+    elapsed: f32,
+    from: Option<Vec3>,
+    release: Option<(Vec3, f32)>,
+}
+
+impl LookOverride {
+    pub fn new(yaw_pitch_roll: Vec3) -> Self {
+        Self {
+            yaw_pitch_roll,
+            duration: None,
+            blend_in: 0.25,
+            blend_out: 0.25,
+            restore: false,
+            source: None,
+            released: false,
+            elapsed: 0.0,
+            from: None,
+            release: None,
+        }
+    }
+
+    pub fn with_duration(mut self, duration: f32) -> Self {
+        self.duration = Some(duration);
+        self
+    }
+
+    pub fn with_blend(mut self, blend_in: f32, blend_out: f32) -> Self {
+        self.blend_in = blend_in;
+        self.blend_out = blend_out;
+        self
+    }
+
+    pub fn with_restore(mut self) -> Self {
+        self.restore = true;
+        self
+    }
+
+    pub fn with_source(mut self, source: u32) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    /// Hand control back to the player
+    pub fn release(&mut self) {
+        self.released = true;
+    }
+
+    pub fn is_releasing(&self) -> bool {
+        self.release.is_some()
+    }
+
+    /// Advance the override and return the orientation to use, or None once the player has
+    /// control again
+    fn update(&mut self, dt: f32, current: Vec3) -> Option<Vec3> {
+        let from = *self.from.get_or_insert(current);
+        self.elapsed += dt;
+        let expired = self
+            .duration
+            .map_or(false, |duration| self.elapsed >= duration);
+        if self.release.is_none() && (self.released || expired) {
+            self.release = Some((current, 0.0));
+        }
+
+        if let Some((release_from, release_elapsed)) = self.release.as_mut() {
+            if !self.restore {
+                return None;
+            }
+            *release_elapsed += dt;
+            let t = blend_factor(*release_elapsed, self.blend_out);
+            if t >= 1.0 {
+                return None;
+            }
+            return Some(lerp_angles(*release_from, from, t));
+        }
+
+        let t = blend_factor(self.elapsed, self.blend_in);
+        Some(lerp_angles(from, self.yaw_pitch_roll, t))
+    }
+}
+
+fn blend_factor(elapsed: f32, duration: f32) -> f32 {
+    if duration <= 0.0 {
+        return 1.0;
+    }
+    let t = (elapsed / duration).min(1.0);
+    // Smoothstep so the blend eases in and out
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Wrap an angle in radians to the range [-PI, PI]
+pub fn wrap_angle(angle: f32) -> f32 {
+    let tau = std::f32::consts::TAU;
+    angle - tau * ((angle + std::f32::consts::PI) / tau).floor()
+}
+
+/// Interpolate yaw, pitch and roll, taking the shortest path around the circle
+pub fn lerp_angles(from: Vec3, to: Vec3, t: f32) -> Vec3 {
+    Vec3::new(
+        from.x + wrap_angle(to.x - from.x) * t,
+        from.y + wrap_angle(to.y - from.y) * t,
+        from.z + wrap_angle(to.z - from.z) * t,
+    )
+}
+
 const PITCH_BOUND: f32 = std::f32::consts::FRAC_PI_2 - 1E-3;
 
+/// Per-controller limits on look orientation. Insert it on the character body to override the
+/// default pitch bounds of just under +/-90 degrees. Pitch bounds given the wrong way round are
+/// swapped.
+#[derive(Clone, Copy, Debug)]
+pub struct LookLimits {
+    /// Minimum pitch in radians (looking down)
+    pub pitch_min: f32,
+    /// Maximum pitch in radians (looking up)
+    pub pitch_max: f32,
+    /// Optional yaw limits, e.g. for turrets and seated positions
+    pub yaw: Option<YawLimits>,
+    /// Optional roll input, e.g. for flight
+    pub roll: Option<RollSettings>,
+}
+
+impl Default for LookLimits {
+    fn default() -> Self {
+        Self {
+            pitch_min: -PITCH_BOUND,
+            pitch_max: PITCH_BOUND,
+            yaw: None,
+            roll: None,
+        }
+    }
+}
+
+impl LookLimits {
+    /// The limits allowed by all of `limits`: the highest minimum and lowest maximum pitch, the
+    /// overlap of the yaw ranges and the slowest roll. Disjoint ranges collapse to the highest
+    /// minimum, so the result does not depend on the order. Default limits if there are none
+    pub fn intersection(limits: impl IntoIterator<Item = LookLimits>) -> LookLimits {
+        limits
+            .into_iter()
+            .fold(None, |intersection: Option<LookLimits>, limits| {
+                Some(intersection.map_or(limits, |intersection| intersection.intersect(&limits)))
+            })
+            .unwrap_or_default()
+    }
+
+    fn intersect(&self, other: &LookLimits) -> LookLimits {
+        let (min, max) = self.pitch_range();
+        let (other_min, other_max) = other.pitch_range();
+        let pitch_min = min.max(other_min);
+        let pitch_max = max.min(other_max).max(pitch_min);
+        let yaw = match (self.yaw, other.yaw) {
+            (Some(yaw), Some(other_yaw)) => Some(yaw.intersect(&other_yaw)),
+            (yaw, other_yaw) => yaw.or(other_yaw),
+        };
+        let roll = match (self.roll, other.roll) {
+            (Some(roll), Some(other_roll)) => Some(RollSettings {
+                speed: roll.speed.min(other_roll.speed),
+                max: roll.max.min(other_roll.max),
+                auto_level: roll.auto_level.min(other_roll.auto_level),
+            }),
+            (roll, other_roll) => roll.or(other_roll),
+        };
+        LookLimits {
+            pitch_min,
+            pitch_max,
+            yaw,
+            roll,
+        }
+    }
+
+    fn pitch_range(&self) -> (f32, f32) {
+        (
+            self.pitch_min.min(self.pitch_max),
+            self.pitch_min.max(self.pitch_max),
+        )
+    }
+
+    /// Clamp the yaw and pitch of `yaw_pitch_roll` to the limits
+    pub fn clamp(&self, yaw_pitch_roll: Vec3) -> Vec3 {
+        let (pitch_min, pitch_max) = self.pitch_range();
+        let yaw = self
+            .yaw
+            .map_or(yaw_pitch_roll.x, |yaw| yaw.clamp(yaw_pitch_roll.x));
+        Vec3::new(
+            yaw,
+            yaw_pitch_roll.y.max(pitch_min).min(pitch_max),
+            yaw_pitch_roll.z,
+        )
+    }
+}
+
+/// Yaw limits in radians relative to a reference yaw
+#[derive(Clone, Copy, Debug)]
+pub struct YawLimits {
+    pub reference: f32,
+    pub min: f32,
+    pub max: f32,
+}
+
+impl YawLimits {
+    /// Clamp `yaw` to the limits, keeping it in the same turn so that it does not jump by a
+    /// multiple of 2 PI
+    pub fn clamp(&self, yaw: f32) -> f32 {
+        let offset = wrap_angle(yaw - self.reference);
+        yaw + offset.max(self.min).min(self.max) - offset
+    }
+
+    /// The overlap with `other`, relative to the reference of `self`
+    fn intersect(&self, other: &YawLimits) -> YawLimits {
+        let offset = wrap_angle(other.reference - self.reference);
+        let min = self.min.max(offset + other.min);
+        let max = self.max.min(offset + other.max).max(min);
+        YawLimits {
+            reference: self.reference,
+            min,
+            max,
+        }
+    }
+}
+
+/// Roll driven by the roll keys of the `InputMap`
+#[derive(Clone, Copy, Debug)]
+pub struct RollSettings {
+    /// Roll rate in radians per second while a roll key is held
+    pub speed: f32,
+    /// Maximum absolute roll in radians
+    pub max: f32,
+    /// Rate in radians per second at which roll returns to level with no input. 0 disables
+    pub auto_level: f32,
+}
+
+impl Default for RollSettings {
+    fn default() -> Self {
+        Self {
+            speed: std::f32::consts::PI,
+            max: std::f32::consts::FRAC_PI_2,
+            auto_level: std::f32::consts::FRAC_PI_2,
+        }
+    }
+}
+
+impl RollSettings {
+    fn update(&self, roll: f32, input: f32, dt: f32) -> f32 {
+        let roll = if input.abs() > 1E-6 {
+            roll + input * self.speed * dt
+        } else {
+            let step = self.auto_level * dt;
+            if roll.abs() <= step {
+                0.0
+            } else {
+                roll - step * roll.signum()
+            }
+        };
+        roll.max(-self.max).min(self.max)
+    }
+}
+
 pub fn input_to_look(
+    commands: &mut Commands,
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
     mouse_motion_events: Res<Events<MouseMotion>>,
     mut settings: ResMut<MouseSettings>,
     mut mouse_motion: ResMut<MouseMotionState>,
     mut pitch_events: ResMut<Events<PitchEvent>>,
     mut yaw_events: ResMut<Events<YawEvent>>,
+    mut roll_events: ResMut<Events<RollEvent>>,
     mut look_events: ResMut<Events<LookEvent>>,
     mut look_delta_events: ResMut<Events<LookDeltaEvent>>,
+    mut override_query: Query<(Entity, &mut LookOverride)>,
+    controller_query: Query<(&CharacterController, &LookEntity, Option<&LookLimits>)>,
+    look_query: Query<&LookDirection>,
 ) {
     let mut delta = Vec2::zero();
     for motion in mouse_motion.event_reader.iter(&mouse_motion_events) {
         // NOTE: -= to invert
         delta -= motion.delta;
     }
-    if delta.length_squared() > 1E-6 {
-        delta *= settings.sensitivity;
-        settings.yaw_pitch_roll += delta.extend(0.0);
-        if settings.yaw_pitch_roll.y > PITCH_BOUND {
-            settings.yaw_pitch_roll.y = PITCH_BOUND;
+
+    // Mouse look and overrides drive every look entity, so they are limited by all controllers
+    // that own one
+    let owners = controller_query
+        .iter()
+        .filter(|(_, look_entity, _)| look_query.get(look_entity.0).is_ok())
+        .map(|(controller, _, limits)| (controller, limits.copied().unwrap_or_default()))
+        .collect::<Vec<_>>();
+    let limits = LookLimits::intersection(owners.iter().map(|(_, limits)| *limits));
+
+    if let Some((entity, mut look_override)) = override_query.iter_mut().next() {
+        // Mouse motion during the override is dropped so that it does not snap the view when
+        // control is handed back
+        let previous = settings.yaw_pitch_roll;
+        match look_override.update(time.delta_seconds(), previous) {
+            // Overrides, including blending back when released, stay within the limits
+            Some(yaw_pitch_roll) => settings.yaw_pitch_roll = limits.clamp(yaw_pitch_roll),
+            None => {
+                commands.remove_one::<LookOverride>(entity);
+                return;
+            }
+        }
+        look_delta_events.send(LookDeltaEvent::new(&(settings.yaw_pitch_roll - previous)));
+        look_events.send(LookEvent::new(&settings.yaw_pitch_roll));
+        pitch_events.send(PitchEvent::new(settings.yaw_pitch_roll.y));
+        roll_events.send(RollEvent::new(settings.yaw_pitch_roll.z));
+        yaw_events.send(YawEvent::new(settings.yaw_pitch_roll.x));
+        return;
+    }
+
+    let mut delta = (delta * settings.sensitivity * settings.sensitivity_scale).extend(0.0);
+    if let Some(roll) = limits.roll {
+        // The roll keys of any owner with roll enabled
+        let (mut roll_left, mut roll_right) = (false, false);
+        for (controller, owner_limits) in owners.iter() {
+            if owner_limits.roll.is_some() {
+                roll_left |= keyboard_input.pressed(controller.input_map.key_roll_left);
+                roll_right |= keyboard_input.pressed(controller.input_map.key_roll_right);
+            }
+        }
+        let mut roll_input = 0.0;
+        if roll_left {
+            roll_input -= 1.0;
         }
-        if settings.yaw_pitch_roll.y < -PITCH_BOUND {
-            settings.yaw_pitch_roll.y = -PITCH_BOUND;
+        if roll_right {
+            roll_input += 1.0;
         }
-        look_delta_events.send(LookDeltaEvent::new(&delta.extend(0.0)));
+        let current = settings.yaw_pitch_roll.z;
+        delta.z = roll.update(current, roll_input, time.delta_seconds()) - current;
+    }
+    if delta.length_squared() > 1E-6 {
+        let previous = settings.yaw_pitch_roll;
+        settings.yaw_pitch_roll = limits.clamp(settings.yaw_pitch_roll + delta);
+        look_delta_events.send(LookDeltaEvent::new(&(settings.yaw_pitch_roll - previous)));
         look_events.send(LookEvent::new(&settings.yaw_pitch_roll));
         pitch_events.send(PitchEvent::new(settings.yaw_pitch_roll.y));
+        roll_events.send(RollEvent::new(settings.yaw_pitch_roll.z));
         yaw_events.send(YawEvent::new(settings.yaw_pitch_roll.x));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    #[test]
+    fn wrap_angle_wraps_to_pi() {
+        assert!((wrap_angle(0.5) - 0.5).abs() < 1E-6);
+        assert!((wrap_angle(2.0 * PI + 0.5) - 0.5).abs() < 1E-5);
+        assert!((wrap_angle(-2.0 * PI - 0.5) + 0.5).abs() < 1E-5);
+    }
+
+    #[test]
+    fn lerp_angles_takes_the_shortest_path() {
+        let from = Vec3::new(PI - 0.1, 0.0, 0.0);
+        let to = Vec3::new(-PI + 0.1, 0.0, 0.0);
+        let halfway = lerp_angles(from, to, 0.5);
+        // Through PI rather than back through 0
+        assert!((wrap_angle(halfway.x).abs() - PI).abs() < 1E-4);
+    }
+
+    #[test]
+    fn lerp_angles_endpoints() {
+        let from = Vec3::new(0.1, 0.2, 0.3);
+        let to = Vec3::new(0.4, -0.2, 0.0);
+        assert!((lerp_angles(from, to, 0.0) - from).length() < 1E-6);
+        assert!((lerp_angles(from, to, 1.0) - to).length() < 1E-6);
+    }
+
+    #[test]
+    fn yaw_clamp_keeps_the_turn() {
+        let limits = YawLimits {
+            reference: 0.0,
+            min: -1.0,
+            max: 1.0,
+        };
+        assert!((limits.clamp(2.0 * PI + 1.5) - (2.0 * PI + 1.0)).abs() < 1E-5);
+        assert!((limits.clamp(-2.0 * PI + 0.5) - (-2.0 * PI + 0.5)).abs() < 1E-5);
+    }
+
+    #[test]
+    fn swapped_pitch_bounds_still_clamp() {
+        let limits = LookLimits {
+            pitch_min: 0.5,
+            pitch_max: -0.5,
+            ..Default::default()
+        };
+        let clamped = limits.clamp(Vec3::new(0.0, 1.0, 0.0));
+        assert!((clamped.y - 0.5).abs() < 1E-6);
+        let clamped = limits.clamp(Vec3::new(0.0, -1.0, 0.0));
+        assert!((clamped.y + 0.5).abs() < 1E-6);
+    }
+
+    #[test]
+    fn conflicting_controller_limits_intersect_in_any_order() {
+        let first = LookLimits {
+            pitch_min: -1.0,
+            pitch_max: 0.5,
+            yaw: Some(YawLimits {
+                reference: 0.0,
+                min: -1.0,
+                max: 1.0,
+            }),
+            roll: None,
+        };
+        let second = LookLimits {
+            pitch_min: -0.2,
+            pitch_max: 1.0,
+            yaw: Some(YawLimits {
+                reference: 0.5,
+                min: -1.0,
+                max: 1.0,
+            }),
+            roll: None,
+        };
+        for limits in [
+            LookLimits::intersection(vec![first, second]),
+            LookLimits::intersection(vec![second, first]),
+        ]
+        .iter()
+        {
+            let high = limits.clamp(Vec3::new(2.0, 1.0, 0.0));
+            assert!((high.x - 1.0).abs() < 1E-5);
+            assert!((high.y - 0.5).abs() < 1E-5);
+            let low = limits.clamp(Vec3::new(-2.0, -1.0, 0.0));
+            assert!((low.x + 0.5).abs() < 1E-5);
+            assert!((low.y + 0.2).abs() < 1E-5);
+        }
+
+        // Disjoint pitch ranges collapse to the highest minimum
+        let above = LookLimits {
+            pitch_min: 0.6,
+            pitch_max: 1.0,
+            yaw: None,
+            roll: None,
+        };
+        for limits in [
+            LookLimits::intersection(vec![first, above]),
+            LookLimits::intersection(vec![above, first]),
+        ]
+        .iter()
+        {
+            assert!((limits.clamp(Vec3::zero()).y - 0.6).abs() < 1E-5);
+        }
+    }
+
+    #[test]
+    fn no_limits_are_the_default() {
+        let limits = LookLimits::intersection(Vec::<LookLimits>::new());
+        let clamped = limits.clamp(Vec3::new(0.0, PI, 0.0));
+        assert!((clamped.y - PITCH_BOUND).abs() < 1E-6);
+    }
+}
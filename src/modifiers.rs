@@ -0,0 +1,233 @@
+// Stackable speed modifiers for slows, hastes and roots
+use bevy::prelude::*;
+
+/// Walk, run and jump speeds in m/s
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MovementSpeeds {
+    pub walk: f32,
+    pub run: f32,
+    pub jump: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ModifierValue {
+    /// Scale the speed, e.g. 0.5 for a slow or 1.5 for a haste
+    Multiplier(f32),
+    /// Replace the speed, e.g. 0.0 for a root
+    Override(f32),
+}
+
+impl Default for ModifierValue {
+    fn default() -> Self {
+        ModifierValue::Multiplier(1.0)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SpeedModifier {
+    /// Identifies what applied the modifier. Adding a modifier replaces any from the same source.
+    /// Ids from `u32::MAX - 2` up are reserved for the crate, e.g. `CROUCH_SPEED_SOURCE`
+    pub source: u32,
+    pub walk: ModifierValue,
+    pub run: ModifierValue,
+    pub jump: ModifierValue,
+    /// Time in seconds until the modifier expires. None lasts until removed
+    pub remaining: Option<f32>,
+}
+
+impl SpeedModifier {
+    /// Scale walk and run speed
+    pub fn multiplier(source: u32, multiplier: f32, duration: Option<f32>) -> Self {
+        Self {
+            source,
+            walk: ModifierValue::Multiplier(multiplier),
+            run: ModifierValue::Multiplier(multiplier),
+            jump: ModifierValue::default(),
+            remaining: duration,
+        }
+    }
+
+    /// Stop the character from moving or jumping
+    pub fn root(source: u32, duration: Option<f32>) -> Self {
+        Self {
+            source,
+            walk: ModifierValue::Override(0.0),
+            run: ModifierValue::Override(0.0),
+            jump: ModifierValue::Override(0.0),
+            remaining: duration,
+        }
+    }
+}
+
+/// Insert on the character body to modify its speeds. Multipliers stack by multiplying together.
+/// Overrides take precedence over multipliers and the lowest override wins, so a root always
+/// stops the character.
+#[derive(Default)]
+pub struct SpeedModifiers {
+    pub modifiers: Vec<SpeedModifier>,
+}
+
+impl SpeedModifiers {
+    pub fn add(&mut self, modifier: SpeedModifier) {
+        self.remove(modifier.source);
+        self.modifiers.push(modifier);
+    }
+
+    pub fn remove(&mut self, source: u32) {
+        self.modifiers.retain(|modifier| modifier.source != source);
+    }
+
+    /// Count down durations and drop expired modifiers
+    pub fn update(&mut self, dt: f32) {
+        for modifier in self.modifiers.iter_mut() {
+            if let Some(remaining) = modifier.remaining.as_mut() {
+                *remaining -= dt;
+            }
+        }
+        self.modifiers
+            .retain(|modifier| modifier.remaining.map_or(true, |remaining| remaining > 0.0));
+    }
+
+    /// The speeds after applying all modifiers to `base`
+    pub fn apply(&self, base: MovementSpeeds) -> MovementSpeeds {
+        let combine = |base: f32, value: fn(&SpeedModifier) -> ModifierValue| {
+            let mut multiplier = 1.0;
+            let mut lowest_override: Option<f32> = None;
+            for modifier in self.modifiers.iter() {
+                match value(modifier) {
+                    ModifierValue::Multiplier(value) => multiplier *= value,
+                    ModifierValue::Override(value) => {
+                        lowest_override =
+                            Some(lowest_override.map_or(value, |lowest| lowest.min(value)))
+                    }
+                }
+            }
+            lowest_override.unwrap_or(base * multiplier)
+        };
+        MovementSpeeds {
+            walk: combine(base.walk, |modifier| modifier.walk),
+            run: combine(base.run, |modifier| modifier.run),
+            jump: combine(base.jump, |modifier| modifier.jump),
+        }
+    }
+
+    /// Whether walk and run speed are both stopped, e.g. by a root. Movement modes such as
+    /// dashing and climbing do not start while rooted
+    pub fn rooted(&self) -> bool {
+        let speeds = self.apply(MovementSpeeds {
+            walk: 1.0,
+            run: 1.0,
+            jump: 1.0,
+        });
+        speeds.walk <= 0.0 && speeds.run <= 0.0
+    }
+}
+
+pub fn speed_modifiers(time: Res<Time>, mut query: Query<&mut SpeedModifiers>) {
+    for mut modifiers in query.iter_mut() {
+        modifiers.update(time.delta_seconds());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn base() -> MovementSpeeds {
+        MovementSpeeds {
+            walk: 4.0,
+            run: 8.0,
+            jump: 6.0,
+        }
+    }
+
+    #[test]
+    fn no_modifiers_keeps_base() {
+        assert_eq!(SpeedModifiers::default().apply(base()), base());
+    }
+
+    #[test]
+    fn multipliers_stack() {
+        let mut modifiers = SpeedModifiers::default();
+        modifiers.add(SpeedModifier::multiplier(1, 0.5, None));
+        modifiers.add(SpeedModifier::multiplier(2, 1.5, None));
+        let speeds = modifiers.apply(base());
+        assert!((speeds.walk - 3.0).abs() < 1E-5);
+        assert!((speeds.run - 6.0).abs() < 1E-5);
+        // Multipliers only scale walk and run speed
+        assert!((speeds.jump - 6.0).abs() < 1E-5);
+    }
+
+    #[test]
+    fn same_source_replaces() {
+        let mut modifiers = SpeedModifiers::default();
+        modifiers.add(SpeedModifier::multiplier(1, 0.5, None));
+        modifiers.add(SpeedModifier::multiplier(1, 2.0, None));
+        assert_eq!(modifiers.modifiers.len(), 1);
+        assert!((modifiers.apply(base()).walk - 8.0).abs() < 1E-5);
+    }
+
+    #[test]
+    fn lowest_override_wins_over_multipliers() {
+        let mut modifiers = SpeedModifiers::default();
+        modifiers.add(SpeedModifier::multiplier(1, 2.0, None));
+        modifiers.add(SpeedModifier {
+            source: 2,
+            walk: ModifierValue::Override(3.0),
+            run: ModifierValue::Override(5.0),
+            jump: ModifierValue::default(),
+            remaining: None,
+        });
+        modifiers.add(SpeedModifier {
+            source: 3,
+            walk: ModifierValue::Override(2.0),
+            run: ModifierValue::Override(7.0),
+            jump: ModifierValue::default(),
+            remaining: None,
+        });
+        let speeds = modifiers.apply(base());
+        assert!((speeds.walk - 2.0).abs() < 1E-5);
+        assert!((speeds.run - 5.0).abs() < 1E-5);
+        assert!((speeds.jump - 6.0).abs() < 1E-5);
+    }
+
+    #[test]
+    fn root_stops_everything() {
+        let mut modifiers = SpeedModifiers::default();
+        modifiers.add(SpeedModifier::multiplier(1, 1.5, None));
+        modifiers.add(SpeedModifier::root(2, None));
+        let speeds = modifiers.apply(base());
+        assert_eq!(
+            speeds,
+            MovementSpeeds {
+                walk: 0.0,
+                run: 0.0,
+                jump: 0.0,
+            }
+        );
+    }
+
+    #[test]
+    fn rooted_only_when_walk_and_run_stop() {
+        let mut modifiers = SpeedModifiers::default();
+        assert!(!modifiers.rooted());
+        modifiers.add(SpeedModifier::multiplier(1, 0.5, None));
+        assert!(!modifiers.rooted());
+        modifiers.add(SpeedModifier::root(2, None));
+        assert!(modifiers.rooted());
+        modifiers.remove(2);
+        assert!(!modifiers.rooted());
+    }
+
+    #[test]
+    fn expired_modifiers_are_dropped() {
+        let mut modifiers = SpeedModifiers::default();
+        modifiers.add(SpeedModifier::multiplier(1, 0.5, Some(0.5)));
+        modifiers.add(SpeedModifier::multiplier(2, 0.5, None));
+        modifiers.update(0.25);
+        assert_eq!(modifiers.modifiers.len(), 2);
+        modifiers.update(0.25);
+        assert_eq!(modifiers.modifiers.len(), 1);
+        assert_eq!(modifiers.modifiers[0].source, 2);
+    }
+}
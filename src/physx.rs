@@ -1,4 +1,14 @@
-use crate::{controller::*, events::*};
+use crate::{
+    camera::ShoulderOffset,
+    controller::*,
+    events::*,
+    lean::Lean,
+    ledge::{LedgeGrab, LedgeState},
+    look::LookEntity,
+    probe::{Ground, GroundBounds, RayHit},
+    vault::{measure_obstacle, Vault},
+    wall_run::WallRun,
+};
 use bevy::prelude::*;
 use bevy_prototype_physx::*;
 
@@ -8,6 +18,7 @@ impl Plugin for PhysXKinematicTranslationCharacterControllerPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.add_system(create_mass.system())
             .add_system(constrain_rotation.system())
+            .add_plugin(PhysXProbePlugin)
             .add_system_to_stage(bevy::app::stage::PRE_UPDATE, body_to_velocity.system())
             .add_system_to_stage(bevy::app::stage::UPDATE, controller_to_yaw.system())
             .add_system_to_stage(bevy::app::stage::UPDATE, controller_to_pitch.system());
@@ -20,6 +31,7 @@ impl Plugin for PhysXDynamicImpulseCharacterControllerPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.add_system(create_mass.system())
             .add_system(constrain_rotation.system())
+            .add_plugin(PhysXProbePlugin)
             .add_system_to_stage(bevy::app::stage::PRE_UPDATE, body_to_velocity.system())
             // IMPORTANT: The impulse/force systems MUST run before the physics simulation step, so they
             // either need to be added to the end of PRE_UPDATE or the beginning of UPDATE
@@ -37,6 +49,7 @@ impl Plugin for PhysXDynamicForceCharacterControllerPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.add_system(create_mass.system())
             .add_system(constrain_rotation.system())
+            .add_plugin(PhysXProbePlugin)
             .add_system_to_stage(bevy::app::stage::PRE_UPDATE, body_to_velocity.system())
             // IMPORTANT: The impulse/force systems MUST run before the physics simulation step, so they
             // either need to be added to the end of PRE_UPDATE or the beginning of UPDATE
@@ -49,6 +62,290 @@ impl Plugin for PhysXDynamicForceCharacterControllerPlugin {
     }
 }
 
+/// Ray casts against the PhysX scene that feed the character controller features. Added by the
+/// PhysX character controller plugins.
+pub struct PhysXProbePlugin;
+
+impl Plugin for PhysXProbePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(create_probe_capsule.system())
+            .add_system_to_stage(bevy::app::stage::PRE_UPDATE, ground_probe.system())
+            .add_system_to_stage(bevy::app::stage::PRE_UPDATE, wall_probe.system())
+            .add_system_to_stage(bevy::app::stage::PRE_UPDATE, ledge_probe.system())
+            .add_system_to_stage(bevy::app::stage::PRE_UPDATE, vault_probe.system())
+            .add_system_to_stage(bevy::app::stage::PRE_UPDATE, lean_probe.system())
+            .add_system_to_stage(bevy::app::stage::PRE_UPDATE, shoulder_probe.system());
+    }
+}
+
+/// Capsule of the character body, so that probes can start their rays outside of it. Created
+/// from the PhysX capsule descriptions of `BodyTag` entities by `create_probe_capsule`.
+#[derive(Clone, Copy, Debug)]
+pub struct ProbeCapsule {
+    pub radius: f32,
+    /// Half the height of the cylindrical part of the capsule
+    pub half_height: f32,
+}
+
+impl ProbeCapsule {
+    /// Distance along the ray from `origin` at which it leaves the capsule centred on `center`,
+    /// or 0 when it starts outside of it
+    pub fn exit_distance(&self, center: Vec3, origin: Vec3, direction: Vec3) -> f32 {
+        let p = origin - center;
+        let r2 = self.radius * self.radius;
+        let mut exit = 0.0f32;
+
+        // The capsule is convex, so the ray leaves it where it leaves the last of its parts
+        for cap in [-self.half_height, self.half_height].iter() {
+            let offset = p - Vec3::unit_y() * *cap;
+            let b = direction.dot(offset);
+            let discriminant = b * b - (offset.length_squared() - r2);
+            if discriminant >= 0.0 {
+                exit = exit.max(-b + discriminant.sqrt());
+            }
+        }
+
+        let d_xz = Vec2::new(direction.x, direction.z);
+        let p_xz = Vec2::new(p.x, p.z);
+        let a = d_xz.length_squared();
+        let (t_min, t_max) = if a < 1E-6 {
+            if p_xz.length_squared() > r2 {
+                return exit;
+            }
+            (std::f32::MIN, std::f32::MAX)
+        } else {
+            let b = d_xz.dot(p_xz);
+            let discriminant = b * b - a * (p_xz.length_squared() - r2);
+            if discriminant < 0.0 {
+                return exit;
+            }
+            let root = discriminant.sqrt();
+            ((-b - root) / a, (-b + root) / a)
+        };
+        // Clip the infinite cylinder to the height of the cylindrical part
+        let (y_min, y_max) = if direction.y.abs() < 1E-6 {
+            if p.y.abs() > self.half_height {
+                return exit;
+            }
+            (std::f32::MIN, std::f32::MAX)
+        } else {
+            let t0 = (-self.half_height - p.y) / direction.y;
+            let t1 = (self.half_height - p.y) / direction.y;
+            (t0.min(t1), t0.max(t1))
+        };
+        let upper = t_max.min(y_max);
+        if t_min.max(y_min) <= upper {
+            exit = exit.max(upper);
+        }
+        exit.max(0.0)
+    }
+}
+
+/// Cast a ray and return the closest hit. When `body` is given, the ray starts where it leaves
+/// the character's own capsule so that it does not hit the character itself, and distances are
+/// still measured from `origin`.
+///
+/// NOTE: PhysX actors are not mapped back to entities so `RayHit::entity` is always None. The
+/// ground probe fills it in from `GroundBounds`
+pub fn cast_ray(
+    physx: &PhysX,
+    origin: Vec3,
+    direction: Vec3,
+    max_distance: f32,
+    body: Option<(Vec3, &ProbeCapsule)>,
+) -> Option<RayHit> {
+    // Start just outside of the body
+    let skin = body.map_or(0.0, |(center, capsule)| {
+        capsule.exit_distance(center, origin, direction) + 1E-3
+    });
+    if skin >= max_distance {
+        return None;
+    }
+    physx
+        .scene
+        .raycast(origin + direction * skin, direction, max_distance - skin)
+        .map(|hit| RayHit {
+            entity: None,
+            point: hit.position,
+            normal: hit.normal,
+            distance: hit.distance + skin,
+        })
+}
+
+pub fn create_probe_capsule(
+    commands: &mut Commands,
+    query: Query<
+        (
+            Entity,
+            Option<&PhysXColliderDesc>,
+            Option<&PhysXCapsuleControllerDesc>,
+        ),
+        (With<BodyTag>, Without<ProbeCapsule>),
+    >,
+) {
+    for (entity, collider, controller) in query.iter() {
+        let capsule = match (collider, controller) {
+            (Some(PhysXColliderDesc::Capsule(radius, height)), _) => ProbeCapsule {
+                radius: *radius,
+                half_height: 0.5 * *height,
+            },
+            (_, Some(controller)) => ProbeCapsule {
+                radius: controller.radius,
+                half_height: 0.5 * controller.height,
+            },
+            _ => continue,
+        };
+        commands.insert_one(entity, capsule);
+    }
+}
+
+pub fn ground_probe(
+    physx: Res<PhysX>,
+    mut query: Query<(
+        &GlobalTransform,
+        Option<&ProbeCapsule>,
+        &mut Ground,
+        &mut CharacterController,
+    )>,
+    bounds_query: Query<(Entity, &GroundBounds, &GlobalTransform)>,
+) {
+    for (transform, capsule, mut ground, mut controller) in query.iter_mut() {
+        ground.hit = cast_ray(
+            &physx,
+            transform.translation,
+            -Vec3::unit_y(),
+            ground.max_distance,
+            capsule.map(|capsule| (transform.translation, capsule)),
+        )
+        .map(|mut hit| {
+            hit.entity = bounds_query
+                .iter()
+                .find(|(_, bounds, bounds_transform)| bounds.contains(bounds_transform, hit.point))
+                .map(|(entity, _, _)| entity);
+            hit
+        });
+        if ground.grounded() && controller.velocity.y <= controller.ground_velocity.y {
+            controller.jumping = false;
+        }
+    }
+}
+
+pub fn wall_probe(
+    physx: Res<PhysX>,
+    mut query: Query<(&GlobalTransform, Option<&ProbeCapsule>, &mut WallRun)>,
+) {
+    for (transform, capsule, mut wall_run) in query.iter_mut() {
+        let body = capsule.map(|capsule| (transform.translation, capsule));
+        let cast = |direction: Vec3| {
+            cast_ray(
+                &physx,
+                transform.translation,
+                direction,
+                wall_run.detect_distance,
+                body,
+            )
+        };
+        let left = cast(-wall_run.right);
+        let right = cast(wall_run.right);
+        wall_run.wall_left = left;
+        wall_run.wall_right = right;
+    }
+}
+
+pub fn ledge_probe(
+    physx: Res<PhysX>,
+    mut query: Query<(&GlobalTransform, Option<&ProbeCapsule>, &mut LedgeGrab)>,
+) {
+    for (transform, capsule, mut ledge) in query.iter_mut() {
+        if ledge.state == LedgeState::Mantling {
+            continue;
+        }
+        let body = capsule.map(|capsule| (transform.translation, capsule));
+        // While hanging, look for the ledge in front of its current point
+        let origin = match ledge.state {
+            LedgeState::Hanging => ledge.ledge_point + ledge.ledge_normal * ledge.reach,
+            _ => transform.translation + Vec3::unit_y() * ledge.grab_height,
+        };
+        let wall = match ledge.state {
+            LedgeState::Hanging => cast_ray(
+                &physx,
+                origin - Vec3::unit_y() * 0.1,
+                ledge.forward,
+                2.0 * ledge.reach,
+                body,
+            ),
+            _ => cast_ray(&physx, origin, ledge.forward, ledge.reach, body),
+        };
+        let top = wall.and_then(|wall| {
+            let above = wall.point - wall.normal * 0.1 + Vec3::unit_y() * ledge.grab_range;
+            cast_ray(&physx, above, -Vec3::unit_y(), 2.0 * ledge.grab_range, body)
+        });
+        ledge.wall = wall;
+        ledge.top = top;
+    }
+}
+
+// NOTE: Disabling collisions while vaulting is not supported with PhysX, so the arc has to clear
+// the obstacle. See `Vault::clearance`
+pub fn vault_probe(
+    physx: Res<PhysX>,
+    mut query: Query<(&GlobalTransform, Option<&ProbeCapsule>, &mut Vault)>,
+) {
+    for (transform, capsule, mut vault) in query.iter_mut() {
+        if vault.vaulting {
+            continue;
+        }
+        let body = capsule.map(|capsule| (transform.translation, capsule));
+        let obstacle = measure_obstacle(&vault, transform.translation, |origin, direction, max| {
+            cast_ray(&physx, origin, direction, max, body)
+        });
+        vault.obstacle = obstacle;
+    }
+}
+
+pub fn lean_probe(
+    physx: Res<PhysX>,
+    query: Query<(&GlobalTransform, Option<&ProbeCapsule>, &HeadEntity), With<BodyTag>>,
+    mut lean_query: Query<&mut Lean>,
+) {
+    for (transform, capsule, head_entity) in query.iter() {
+        if let Ok(mut lean) = lean_query.get_mut(head_entity.0) {
+            let body = capsule.map(|capsule| (transform.translation, capsule));
+            let reach = lean.max_offset + lean.radius;
+            let clearance = |direction: Vec3| {
+                cast_ray(&physx, lean.origin, direction, reach, body).map_or(1.0, |hit| {
+                    ((hit.distance - lean.radius) / lean.max_offset).max(0.0)
+                })
+            };
+            let left = clearance(-lean.right);
+            let right = clearance(lean.right);
+            lean.clearance_left = left;
+            lean.clearance_right = right;
+        }
+    }
+}
+
+pub fn shoulder_probe(
+    physx: Res<PhysX>,
+    query: Query<(&GlobalTransform, Option<&ProbeCapsule>, &LookEntity), With<BodyTag>>,
+    mut shoulder_query: Query<&mut ShoulderOffset>,
+) {
+    for (transform, capsule, look_entity) in query.iter() {
+        if let Ok(mut shoulder) = shoulder_query.get_mut(look_entity.0) {
+            let body = capsule.map(|capsule| (transform.translation, capsule));
+            let reach = shoulder.offset + shoulder.radius;
+            let clearance = |direction: Vec3| {
+                cast_ray(&physx, shoulder.origin, direction, reach, body)
+                    .map_or(f32::MAX, |hit| hit.distance)
+            };
+            let left = clearance(-shoulder.right);
+            let right = clearance(shoulder.right);
+            shoulder.clearance_left = left;
+            shoulder.clearance_right = right;
+        }
+    }
+}
+
 pub struct ConstrainedTag;
 
 pub fn constrain_rotation(
@@ -0,0 +1,95 @@
+// Moving platforms
+use crate::{
+    controller::CharacterController,
+    events::{LookEvent, YawEvent},
+    look::MouseSettings,
+    probe::Ground,
+};
+use bevy::prelude::*;
+
+/// Insert on the character body alongside `Ground` to be carried by whatever the character stands
+/// on. The velocity of the ground under the character is measured from the motion of its
+/// `GlobalTransform`, so it works for platforms moved by the physics engine or by animation. The
+/// velocity is kept while airborne so that jumping off a platform preserves its momentum.
+///
+/// This needs `Ground::hit` to name the ground entity. With PhysX, add `GroundBounds` to the
+/// platforms so that the ground probe can find them.
+pub struct PlatformRider {
+    /// Rotate the character's yaw with the platform
+    pub rotate_yaw: bool,
+    /// The entity being stood on
+    pub platform: Option<Entity>,
+    /// Velocity of the platform at the character's position
+    pub velocity: Vec3,
+    /// Yaw rate of the platform in radians per second
+    pub yaw_rate: f32,
+    previous: Option<(Vec3, Quat)>,
+}
+
+impl Default for PlatformRider {
+    fn default() -> Self {
+        Self {
+            rotate_yaw: true,
+            platform: None,
+            velocity: Vec3::zero(),
+            yaw_rate: 0.0,
+            previous: None,
+        }
+    }
+}
+
+pub fn moving_platforms(
+    time: Res<Time>,
+    mut settings: ResMut<MouseSettings>,
+    mut yaw_events: ResMut<Events<YawEvent>>,
+    mut look_events: ResMut<Events<LookEvent>>,
+    mut query: Query<(
+        &GlobalTransform,
+        &Ground,
+        &mut CharacterController,
+        &mut PlatformRider,
+    )>,
+    platform_query: Query<&GlobalTransform>,
+) {
+    let dt = time.delta_seconds();
+    if dt <= 0.0 {
+        return;
+    }
+    for (transform, ground, mut controller, mut rider) in query.iter_mut() {
+        let platform = ground.hit.and_then(|hit| hit.entity);
+        if platform != rider.platform {
+            rider.platform = platform;
+            rider.previous = None;
+        }
+
+        match platform.and_then(|entity| platform_query.get(entity).ok()) {
+            Some(platform_transform) => {
+                if let Some((previous_translation, previous_rotation)) = rider.previous {
+                    // Move the character's position rigidly with the platform
+                    let rotation = platform_transform.rotation * previous_rotation.conjugate();
+                    let offset = transform.translation - previous_translation;
+                    let carried = platform_transform.translation + rotation * offset;
+                    rider.velocity = (carried - transform.translation) / dt;
+
+                    let forward = rotation * Vec3::unit_z();
+                    let yaw_delta = forward.x.atan2(forward.z);
+                    rider.yaw_rate = yaw_delta / dt;
+                    if rider.rotate_yaw && yaw_delta.abs() > 1E-6 {
+                        settings.yaw_pitch_roll.x += yaw_delta;
+                        look_events.send(LookEvent::new(&settings.yaw_pitch_roll));
+                        yaw_events.send(YawEvent::new(settings.yaw_pitch_roll.x));
+                    }
+                }
+                rider.previous =
+                    Some((platform_transform.translation, platform_transform.rotation));
+            }
+            None if ground.grounded() => {
+                rider.velocity = Vec3::zero();
+                rider.yaw_rate = 0.0;
+            }
+            // Airborne, keep the momentum from the last platform
+            None => rider.yaw_rate = 0.0,
+        }
+        controller.ground_velocity = rider.velocity;
+    }
+}
@@ -0,0 +1,59 @@
+// Results of ray casts against the physics world, filled in by the physics backends
+use crate::volume::contains_point;
+use bevy::prelude::*;
+
+#[derive(Clone, Copy, Debug)]
+pub struct RayHit {
+    /// The entity that was hit, if the backend can map its shapes back to entities
+    pub entity: Option<Entity>,
+    pub point: Vec3,
+    pub normal: Vec3,
+    pub distance: f32,
+}
+
+/// Ground contact below the character body, written by the physics backends. Insert on the body.
+/// While grounded and not moving upwards the backends also clear `CharacterController::jumping`.
+pub struct Ground {
+    /// Length of the downward ray from the body origin. This should be slightly more than the
+    /// distance from the body origin to the bottom of its collider
+    pub max_distance: f32,
+    pub hit: Option<RayHit>,
+}
+
+impl Default for Ground {
+    fn default() -> Self {
+        Self {
+            max_distance: 1.25,
+            hit: None,
+        }
+    }
+}
+
+impl Ground {
+    pub fn new(max_distance: f32) -> Self {
+        Self {
+            max_distance,
+            hit: None,
+        }
+    }
+
+    pub fn grounded(&self) -> bool {
+        self.hit.is_some()
+    }
+}
+
+/// Insert on ground colliders alongside a `GlobalTransform` for backends that cannot map the
+/// shapes they hit back to entities, currently PhysX. The ground hit is given the entity whose
+/// box, `half_extents` in the entity's space, contains the hit point, so that moving platforms and
+/// surface properties work with every backend.
+pub struct GroundBounds {
+    pub half_extents: Vec3,
+}
+
+impl GroundBounds {
+    pub fn contains(&self, transform: &GlobalTransform, point: Vec3) -> bool {
+        // Hit points lie on the surface, so allow for rounding
+        let margin = 1E-2;
+        contains_point(self.half_extents + Vec3::splat(margin), transform, point)
+    }
+}
@@ -1,8 +1,23 @@
-use crate::{controller::*, events::*};
-use bevy::prelude::*;
+use crate::{
+    camera::ShoulderOffset,
+    controller::*,
+    events::*,
+    lean::Lean,
+    ledge::{LedgeGrab, LedgeState},
+    look::LookEntity,
+    probe::{Ground, RayHit},
+    vault::{measure_obstacle, Vault},
+    wall_run::WallRun,
+};
+use bevy::{prelude::*, utils::HashMap};
 use bevy_rapier3d::{
     physics::RigidBodyHandleComponent,
-    rapier::{dynamics::RigidBodySet, math::Vector},
+    rapier::{
+        dynamics::{RigidBodyHandle, RigidBodySet},
+        geometry::{ColliderSet, InteractionGroups, Ray},
+        math::{Point, Vector},
+        pipeline::QueryPipeline,
+    },
 };
 
 pub struct RapierDynamicImpulseCharacterControllerPlugin;
@@ -14,12 +29,14 @@ impl Plugin for RapierDynamicImpulseCharacterControllerPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.add_plugin(CharacterControllerPlugin)
             .add_system_to_stage(bevy::app::stage::PRE_UPDATE, create_mass.system())
-            .add_stage_before(
-                PROCESS_INPUT_EVENTS,
+            // NOTE: Velocity must be updated before the MOVEMENT_MODES stage
+            .add_stage_after(
+                bevy::app::stage::PRE_UPDATE,
                 UPDATE_VELOCITY,
                 SystemStage::parallel(),
             )
             .add_system_to_stage(UPDATE_VELOCITY, body_to_velocity.system())
+            .add_plugin(RapierProbePlugin)
             .add_stage_after(PROCESS_INPUT_EVENTS, APPLY_INPUT, SystemStage::parallel())
             .add_system_to_stage(APPLY_INPUT, controller_to_rapier_dynamic_impulse.system())
             .add_system_to_stage(bevy::app::stage::UPDATE, controller_to_yaw.system())
@@ -33,12 +50,14 @@ impl Plugin for RapierDynamicForceCharacterControllerPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.add_plugin(CharacterControllerPlugin)
             .add_system_to_stage(bevy::app::stage::PRE_UPDATE, create_mass.system())
-            .add_stage_before(
-                PROCESS_INPUT_EVENTS,
+            // NOTE: Velocity must be updated before the MOVEMENT_MODES stage
+            .add_stage_after(
+                bevy::app::stage::PRE_UPDATE,
                 UPDATE_VELOCITY,
                 SystemStage::parallel(),
             )
             .add_system_to_stage(UPDATE_VELOCITY, body_to_velocity.system())
+            .add_plugin(RapierProbePlugin)
             .add_stage_after(PROCESS_INPUT_EVENTS, APPLY_INPUT, SystemStage::parallel())
             .add_system_to_stage(APPLY_INPUT, controller_to_rapier_dynamic_force.system())
             .add_system_to_stage(bevy::app::stage::UPDATE, controller_to_yaw.system())
@@ -46,6 +65,267 @@ impl Plugin for RapierDynamicForceCharacterControllerPlugin {
     }
 }
 
+/// Ray casts against the Rapier world that feed the character controller features. Added by the
+/// Rapier character controller plugins and must run after they have added `UPDATE_VELOCITY`.
+pub struct RapierProbePlugin;
+
+impl Plugin for RapierProbePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_to_stage(UPDATE_VELOCITY, ground_probe.system())
+            .add_system_to_stage(UPDATE_VELOCITY, wall_probe.system())
+            .add_system_to_stage(UPDATE_VELOCITY, ledge_probe.system())
+            .add_system_to_stage(UPDATE_VELOCITY, vault_probe.system())
+            .add_system_to_stage(UPDATE_VELOCITY, vault_collisions.system())
+            .add_system_to_stage(UPDATE_VELOCITY, lean_probe.system())
+            .add_system_to_stage(UPDATE_VELOCITY, shoulder_probe.system());
+    }
+}
+
+/// Cast a ray and return the closest hit, ignoring colliders attached to `exclude`
+pub fn cast_ray(
+    query_pipeline: &QueryPipeline,
+    colliders: &ColliderSet,
+    origin: Vec3,
+    direction: Vec3,
+    max_distance: f32,
+    exclude: Option<RigidBodyHandle>,
+) -> Option<RayHit> {
+    let ray = Ray::new(
+        Point::new(origin.x, origin.y, origin.z),
+        Vector::new(direction.x, direction.y, direction.z),
+    );
+    let mut closest: Option<RayHit> = None;
+    query_pipeline.interferences_with_ray(
+        colliders,
+        &ray,
+        max_distance,
+        InteractionGroups::all(),
+        |_, collider, intersection| {
+            if Some(collider.parent()) == exclude {
+                return true;
+            }
+            if closest.map_or(true, |hit| intersection.toi < hit.distance) {
+                let normal = intersection.normal;
+                closest = Some(RayHit {
+                    entity: Some(Entity::from_bits(collider.user_data as u64)),
+                    point: origin + direction * intersection.toi,
+                    normal: Vec3::new(normal[0], normal[1], normal[2]),
+                    distance: intersection.toi,
+                });
+            }
+            true
+        },
+    );
+    closest
+}
+
+pub fn ground_probe(
+    query_pipeline: Res<QueryPipeline>,
+    colliders: Res<ColliderSet>,
+    mut query: Query<(
+        &RigidBodyHandleComponent,
+        &GlobalTransform,
+        &mut Ground,
+        &mut CharacterController,
+    )>,
+) {
+    for (body_handle, transform, mut ground, mut controller) in query.iter_mut() {
+        ground.hit = cast_ray(
+            &query_pipeline,
+            &colliders,
+            transform.translation,
+            -Vec3::unit_y(),
+            ground.max_distance,
+            Some(body_handle.handle()),
+        );
+        if ground.grounded() && controller.velocity.y <= controller.ground_velocity.y {
+            controller.jumping = false;
+        }
+    }
+}
+
+pub fn wall_probe(
+    query_pipeline: Res<QueryPipeline>,
+    colliders: Res<ColliderSet>,
+    mut query: Query<(&RigidBodyHandleComponent, &GlobalTransform, &mut WallRun)>,
+) {
+    for (body_handle, transform, mut wall_run) in query.iter_mut() {
+        let cast = |direction: Vec3| {
+            cast_ray(
+                &query_pipeline,
+                &colliders,
+                transform.translation,
+                direction,
+                wall_run.detect_distance,
+                Some(body_handle.handle()),
+            )
+        };
+        let left = cast(-wall_run.right);
+        let right = cast(wall_run.right);
+        wall_run.wall_left = left;
+        wall_run.wall_right = right;
+    }
+}
+
+pub fn ledge_probe(
+    query_pipeline: Res<QueryPipeline>,
+    colliders: Res<ColliderSet>,
+    mut query: Query<(&RigidBodyHandleComponent, &GlobalTransform, &mut LedgeGrab)>,
+) {
+    for (body_handle, transform, mut ledge) in query.iter_mut() {
+        if ledge.state == LedgeState::Mantling {
+            continue;
+        }
+        let cast = |origin: Vec3, direction: Vec3, max_distance: f32| {
+            cast_ray(
+                &query_pipeline,
+                &colliders,
+                origin,
+                direction,
+                max_distance,
+                Some(body_handle.handle()),
+            )
+        };
+        // While hanging, look for the ledge in front of its current point
+        let origin = match ledge.state {
+            LedgeState::Hanging => ledge.ledge_point + ledge.ledge_normal * ledge.reach,
+            _ => transform.translation + Vec3::unit_y() * ledge.grab_height,
+        };
+        let wall = match ledge.state {
+            LedgeState::Hanging => cast(
+                origin - Vec3::unit_y() * 0.1,
+                ledge.forward,
+                2.0 * ledge.reach,
+            ),
+            _ => cast(origin, ledge.forward, ledge.reach),
+        };
+        let top = wall.and_then(|wall| {
+            let above = wall.point - wall.normal * 0.1 + Vec3::unit_y() * ledge.grab_range;
+            cast(above, -Vec3::unit_y(), 2.0 * ledge.grab_range)
+        });
+        ledge.wall = wall;
+        ledge.top = top;
+    }
+}
+
+pub fn vault_probe(
+    query_pipeline: Res<QueryPipeline>,
+    colliders: Res<ColliderSet>,
+    mut query: Query<(&RigidBodyHandleComponent, &GlobalTransform, &mut Vault)>,
+) {
+    for (body_handle, transform, mut vault) in query.iter_mut() {
+        if vault.vaulting {
+            continue;
+        }
+        let obstacle = measure_obstacle(&vault, transform.translation, |origin, direction, max| {
+            cast_ray(
+                &query_pipeline,
+                &colliders,
+                origin,
+                direction,
+                max,
+                Some(body_handle.handle()),
+            )
+        });
+        vault.obstacle = obstacle;
+    }
+}
+
+/// Disable collisions of the character's colliders while vaulting
+pub fn vault_collisions(
+    mut disabled: Local<HashMap<Entity, InteractionGroups>>,
+    bodies: Res<RigidBodySet>,
+    mut colliders: ResMut<ColliderSet>,
+    query: Query<(Entity, &RigidBodyHandleComponent, &Vault)>,
+) {
+    for (entity, body_handle, vault) in query.iter() {
+        let body = bodies
+            .get(body_handle.handle())
+            .expect("Failed to get RigidBody");
+        let groups = match (vault.vaulting, disabled.get(&entity).copied()) {
+            (true, None) => {
+                let groups = body
+                    .colliders()
+                    .first()
+                    .and_then(|handle| colliders.get(*handle))
+                    .map_or(InteractionGroups::all(), |collider| {
+                        collider.collision_groups()
+                    });
+                disabled.insert(entity, groups);
+                InteractionGroups::none()
+            }
+            (false, Some(groups)) => {
+                disabled.remove(&entity);
+                groups
+            }
+            _ => continue,
+        };
+        for handle in body.colliders() {
+            if let Some(collider) = colliders.get_mut(*handle) {
+                collider.set_collision_groups(groups);
+            }
+        }
+    }
+}
+
+pub fn lean_probe(
+    query_pipeline: Res<QueryPipeline>,
+    colliders: Res<ColliderSet>,
+    query: Query<(&RigidBodyHandleComponent, &HeadEntity), With<BodyTag>>,
+    mut lean_query: Query<&mut Lean>,
+) {
+    for (body_handle, head_entity) in query.iter() {
+        if let Ok(mut lean) = lean_query.get_mut(head_entity.0) {
+            let reach = lean.max_offset + lean.radius;
+            let clearance = |direction: Vec3| {
+                cast_ray(
+                    &query_pipeline,
+                    &colliders,
+                    lean.origin,
+                    direction,
+                    reach,
+                    Some(body_handle.handle()),
+                )
+                .map_or(1.0, |hit| {
+                    ((hit.distance - lean.radius) / lean.max_offset).max(0.0)
+                })
+            };
+            let left = clearance(-lean.right);
+            let right = clearance(lean.right);
+            lean.clearance_left = left;
+            lean.clearance_right = right;
+        }
+    }
+}
+
+pub fn shoulder_probe(
+    query_pipeline: Res<QueryPipeline>,
+    colliders: Res<ColliderSet>,
+    query: Query<(&RigidBodyHandleComponent, &LookEntity), With<BodyTag>>,
+    mut shoulder_query: Query<&mut ShoulderOffset>,
+) {
+    for (body_handle, look_entity) in query.iter() {
+        if let Ok(mut shoulder) = shoulder_query.get_mut(look_entity.0) {
+            let reach = shoulder.offset + shoulder.radius;
+            let clearance = |direction: Vec3| {
+                cast_ray(
+                    &query_pipeline,
+                    &colliders,
+                    shoulder.origin,
+                    direction,
+                    reach,
+                    Some(body_handle.handle()),
+                )
+                .map_or(f32::MAX, |hit| hit.distance)
+            };
+            let left = clearance(-shoulder.right);
+            let right = clearance(shoulder.right);
+            shoulder.clearance_left = left;
+            shoulder.clearance_right = right;
+        }
+    }
+}
+
 pub fn create_mass(
     commands: &mut Commands,
     bodies: Res<RigidBodySet>,
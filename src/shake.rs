@@ -0,0 +1,107 @@
+// Trauma driven camera shake layered on top of the camera boom
+use crate::{
+    camera::{CameraBoom, CameraOffset},
+    events::TraumaEvent,
+};
+use bevy::prelude::*;
+
+/// Insert on the `CameraTag` entity. Trauma is added through `TraumaEvent`s or `add_trauma()` and
+/// decays over time. The shake intensity is the square of the trauma.
+pub struct CameraShake {
+    /// Current trauma in [0, 1]
+    pub trauma: f32,
+    /// Trauma lost per second
+    pub decay: f32,
+    /// Maximum translation in metres along the camera's right / up / back axes
+    pub max_translation: Vec3,
+    /// Maximum yaw / pitch / roll in radians
+    pub max_rotation: Vec3,
+    /// Frequency of the noise in Hz
+    pub frequency: f32,
+    /// Scale applied to the whole shake, e.g. 0 to disable it for accessibility
+    pub intensity: f32,
+    time: f32,
+}
+
+impl Default for CameraShake {
+    fn default() -> Self {
+        Self {
+            trauma: 0.0,
+            decay: 0.8,
+            max_translation: Vec3::new(0.1, 0.1, 0.05),
+            max_rotation: Vec3::new(0.05, 0.05, 0.1),
+            frequency: 15.0,
+            intensity: 1.0,
+            time: 0.0,
+        }
+    }
+}
+
+impl CameraShake {
+    pub fn add_trauma(&mut self, trauma: f32) {
+        self.trauma = (self.trauma + trauma).max(0.0).min(1.0);
+    }
+
+    pub fn shake(&self) -> f32 {
+        self.trauma * self.trauma * self.intensity
+    }
+}
+
+#[derive(Default)]
+pub struct CameraShakeState {
+    event_reader: EventReader<TraumaEvent>,
+}
+
+pub fn camera_shake(
+    time: Res<Time>,
+    trauma_events: Res<Events<TraumaEvent>>,
+    mut state: ResMut<CameraShakeState>,
+    mut query: Query<(&mut CameraShake, &CameraBoom, &mut CameraOffset)>,
+) {
+    let mut trauma = 0.0;
+    for event in state.event_reader.iter(&trauma_events) {
+        trauma += **event;
+    }
+
+    let dt = time.delta_seconds();
+    for (mut shake, boom, mut offset) in query.iter_mut() {
+        shake.add_trauma(trauma);
+        shake.time += dt;
+
+        let amount = shake.shake();
+        if amount > 1E-6 {
+            let t = shake.time * shake.frequency;
+            let translation =
+                amount * shake.max_translation * Vec3::new(noise(t, 0), noise(t, 1), noise(t, 2));
+            let rotation =
+                amount * shake.max_rotation * Vec3::new(noise(t, 3), noise(t, 4), noise(t, 5));
+            offset.add(
+                boom.rotation * translation,
+                Quat::from_rotation_ypr(rotation.x, rotation.y, rotation.z),
+            );
+        }
+
+        let decay = shake.decay * dt;
+        shake.add_trauma(-decay);
+    }
+}
+
+/// Pseudo-random gradient in [-1, 1] for an integer lattice point of a noise channel
+fn gradient(i: i32, channel: u32) -> f32 {
+    let mut x = (i as u32) ^ channel.wrapping_mul(0x9E37_79B9);
+    x = (x ^ (x >> 16)).wrapping_mul(0x7FEB_352D);
+    x = (x ^ (x >> 15)).wrapping_mul(0x846C_A68B);
+    x ^= x >> 16;
+    (x as f32 / u32::MAX as f32) * 2.0 - 1.0
+}
+
+/// One dimensional Perlin noise in roughly [-1, 1]
+fn noise(x: f32, channel: u32) -> f32 {
+    let i = x.floor();
+    let f = x - i;
+    let n0 = gradient(i as i32, channel) * f;
+    let n1 = gradient(i as i32 + 1, channel) * (f - 1.0);
+    // Quintic fade curve
+    let u = f * f * f * (f * (f * 6.0 - 15.0) + 10.0);
+    2.0 * (n0 + (n1 - n0) * u)
+}
@@ -0,0 +1,200 @@
+// Crouch-sliding
+use crate::{
+    controller::{CharacterController, MovementMode},
+    look::{LookDirection, LookEntity},
+    modifiers::{SpeedModifier, SpeedModifiers},
+    probe::Ground,
+};
+use bevy::prelude::*;
+
+/// `SpeedModifier::source` of the crouching slow down. Reserved, like the other ids from
+/// `u32::MAX - 2` up, so gameplay code should use lower ids
+pub const CROUCH_SPEED_SOURCE: u32 = u32::MAX;
+
+/// Insert on the character body alongside `Ground`. Pressing crouch while running starts a slide
+/// that keeps the character's momentum with little friction, speeds up down slopes and only
+/// allows gentle steering. The slide ends when the character slows below `exit_speed`, jumps or
+/// leaves the ground. The head is lowered while sliding and while crouch is held afterwards, and
+/// crouching multiplies walk and run speed by `crouch_speed` when the body has `SpeedModifiers`.
+pub struct Slide {
+    /// Horizontal speed in m/s needed to start a slide
+    pub min_speed: f32,
+    /// Speed in m/s below which the slide ends
+    pub exit_speed: f32,
+    /// Maximum sliding speed in m/s
+    pub max_speed: f32,
+    /// Fraction of the velocity lost per second
+    pub friction: f32,
+    /// Multiplier on gravity along the slope of the ground
+    pub slope_acceleration: f32,
+    /// Rate in radians per second at which input turns the slide
+    pub steering: f32,
+    /// Distance in metres to lower the head while sliding or crouching
+    pub head_drop: f32,
+    /// Speed in m/s at which the head is lowered and raised
+    pub head_speed: f32,
+    /// Multiplier on walk and run speed while crouching
+    pub crouch_speed: f32,
+    pub sliding: bool,
+    /// Whether crouch is held while not sliding
+    pub crouching: bool,
+    head_amount: f32,
+}
+
+impl Default for Slide {
+    fn default() -> Self {
+        Self {
+            min_speed: 6.0,
+            exit_speed: 3.0,
+            max_speed: 15.0,
+            friction: 0.5,
+            slope_acceleration: 1.0,
+            steering: 1.0,
+            head_drop: 0.6,
+            head_speed: 4.0,
+            crouch_speed: 0.5,
+            sliding: false,
+            crouching: false,
+            head_amount: 0.0,
+        }
+    }
+}
+
+impl Slide {
+    /// Current distance in metres that the head is lowered by
+    pub fn head_offset(&self) -> f32 {
+        self.head_amount
+    }
+}
+
+pub fn slide(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut query: Query<(
+        &LookEntity,
+        &Ground,
+        &mut CharacterController,
+        &mut Slide,
+        Option<&SpeedModifiers>,
+    )>,
+    look_direction_query: Query<&LookDirection>,
+) {
+    let xz = Vec3::new(1.0, 0.0, 1.0);
+    for (look_entity, ground, mut controller, mut slide, modifiers) in query.iter_mut() {
+        let input_map = &controller.input_map;
+        let crouch_held = keyboard_input.pressed(input_map.key_crouch);
+        let start = keyboard_input.just_pressed(input_map.key_crouch)
+            && keyboard_input.pressed(input_map.key_run)
+            && !modifiers.map_or(false, |modifiers| modifiers.rooted());
+        let jump = keyboard_input.just_pressed(input_map.key_jump);
+        let mut input = Vec3::zero();
+        if keyboard_input.pressed(input_map.key_forward) {
+            input += Vec3::unit_z();
+        }
+        if keyboard_input.pressed(input_map.key_backward) {
+            input -= Vec3::unit_z();
+        }
+        if keyboard_input.pressed(input_map.key_right) {
+            input += Vec3::unit_x();
+        }
+        if keyboard_input.pressed(input_map.key_left) {
+            input -= Vec3::unit_x();
+        }
+
+        let normal = match ground.hit {
+            Some(hit) if !controller.jumping => hit.normal,
+            _ => {
+                slide.sliding = false;
+                slide.crouching = false;
+                continue;
+            }
+        };
+        let velocity = controller.velocity - controller.ground_velocity;
+
+        if !slide.sliding {
+            slide.crouching = crouch_held;
+            if !start || (velocity * xz).length() < slide.min_speed {
+                continue;
+            }
+            slide.sliding = true;
+            slide.crouching = false;
+        }
+        if jump {
+            // Let the regular jump take over
+            slide.sliding = false;
+            continue;
+        }
+
+        let dt = controller.dt;
+        // Follow the ground and accelerate downhill
+        let mut velocity = velocity - normal * normal.dot(velocity);
+        let gravity = -Vec3::unit_y() * controller.gravity;
+        let downhill = gravity - normal * normal.dot(gravity);
+        velocity += downhill * slide.slope_acceleration * dt;
+        velocity *= (1.0 - slide.friction * dt).max(0.0);
+
+        // Turn gently towards the input direction
+        if input.length_squared() > 1E-6 && velocity.length_squared() > 1E-6 {
+            let look = look_direction_query
+                .get_component::<LookDirection>(look_entity.0)
+                .expect("Failed to get LookDirection from Entity");
+            let forward = (look.forward * xz).normalize();
+            let right = (look.right * xz).normalize();
+            let desired = forward * input.z + right * input.x;
+            let desired = (desired - normal * normal.dot(desired)).normalize();
+            let direction = velocity.normalize();
+            let angle = direction.dot(desired).max(-1.0).min(1.0).acos();
+            let sign = direction.cross(desired).dot(normal).signum();
+            let step = angle.min(slide.steering * dt) * sign;
+            velocity = Quat::from_axis_angle(normal, step) * velocity;
+        }
+
+        let speed = velocity.length();
+        if speed > slide.max_speed {
+            velocity *= slide.max_speed / speed;
+        }
+        if speed < slide.exit_speed {
+            // Back to crouching, or running / walking when crouch was released
+            slide.sliding = false;
+            slide.crouching = crouch_held;
+            continue;
+        }
+        controller.override_velocity(MovementMode::Slide, velocity);
+    }
+}
+
+/// Move the head drop towards its target while sliding or crouching. The head translation is
+/// composed from it by `head_translation`.
+pub fn slide_head(time: Res<Time>, mut query: Query<&mut Slide>) {
+    for mut slide in query.iter_mut() {
+        let target = if slide.sliding || slide.crouching {
+            slide.head_drop
+        } else {
+            0.0
+        };
+        let step = slide.head_speed * time.delta_seconds();
+        slide.head_amount = if (target - slide.head_amount).abs() <= step {
+            target
+        } else {
+            slide.head_amount + step * (target - slide.head_amount).signum()
+        };
+    }
+}
+
+/// Slow the character down while crouching, including after a slide
+pub fn crouch_speed(mut query: Query<(&Slide, &mut SpeedModifiers)>) {
+    for (slide, mut modifiers) in query.iter_mut() {
+        let active = modifiers
+            .modifiers
+            .iter()
+            .any(|modifier| modifier.source == CROUCH_SPEED_SOURCE);
+        if slide.crouching && !active {
+            modifiers.add(SpeedModifier::multiplier(
+                CROUCH_SPEED_SOURCE,
+                slide.crouch_speed,
+                None,
+            ));
+        } else if !slide.crouching && active {
+            modifiers.remove(CROUCH_SPEED_SOURCE);
+        }
+    }
+}
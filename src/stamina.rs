@@ -0,0 +1,98 @@
+// Sprint stamina
+use crate::events::{StaminaDepletedEvent, StaminaRecoveredEvent};
+use bevy::prelude::*;
+
+/// Insert on the character body to limit sprinting. Stamina drains while running and moving and
+/// regenerates after `regen_delay` seconds without use. Once it runs out, sprinting is blocked
+/// until it has recovered to `recovery_threshold`. Jumps and dashes can optionally cost stamina
+/// and are blocked when there is not enough. Jumps are charged when they take off, from the
+/// ground or from a wall, ledge, ladder or water.
+pub struct Stamina {
+    pub max: f32,
+    pub current: f32,
+    /// Stamina used per second while sprinting
+    pub drain_rate: f32,
+    /// Stamina regained per second
+    pub regen_rate: f32,
+    /// Time in seconds after using stamina before it regenerates
+    pub regen_delay: f32,
+    /// Stamina needed to sprint again after running out
+    pub recovery_threshold: f32,
+    /// Stamina used per jump. 0 makes jumping free
+    pub jump_cost: f32,
+    /// Stamina used per dash. 0 makes dashing free
+    pub dash_cost: f32,
+    /// Whether stamina ran out and has not yet recovered
+    pub exhausted: bool,
+    regen_remaining: f32,
+}
+
+impl Default for Stamina {
+    fn default() -> Self {
+        Self {
+            max: 100.0,
+            current: 100.0,
+            drain_rate: 20.0,
+            regen_rate: 25.0,
+            regen_delay: 1.0,
+            recovery_threshold: 30.0,
+            jump_cost: 0.0,
+            dash_cost: 0.0,
+            exhausted: false,
+            regen_remaining: 0.0,
+        }
+    }
+}
+
+impl Stamina {
+    pub fn can_sprint(&self) -> bool {
+        !self.exhausted && self.current > 0.0
+    }
+
+    /// Use stamina continuously, e.g. while sprinting
+    pub fn drain(&mut self, amount: f32) {
+        self.current = (self.current - amount).max(0.0);
+        self.regen_remaining = self.regen_delay;
+    }
+
+    /// Whether there is enough stamina to jump. The cost is charged by `input_to_events` once the
+    /// jump takes off
+    pub fn can_jump(&self) -> bool {
+        self.current >= self.jump_cost
+    }
+
+    /// Use `cost` stamina if there is enough, returning whether it was used
+    pub fn try_spend(&mut self, cost: f32) -> bool {
+        if cost <= 0.0 {
+            return true;
+        }
+        if self.current < cost {
+            return false;
+        }
+        self.drain(cost);
+        true
+    }
+}
+
+pub fn stamina(
+    time: Res<Time>,
+    mut depleted_events: ResMut<Events<StaminaDepletedEvent>>,
+    mut recovered_events: ResMut<Events<StaminaRecoveredEvent>>,
+    mut query: Query<(Entity, &mut Stamina)>,
+) {
+    let dt = time.delta_seconds();
+    for (entity, mut stamina) in query.iter_mut() {
+        stamina.regen_remaining -= dt;
+        if stamina.regen_remaining <= 0.0 {
+            stamina.current = (stamina.current + stamina.regen_rate * dt).min(stamina.max);
+        }
+
+        if !stamina.exhausted && stamina.current <= 0.0 {
+            stamina.exhausted = true;
+            depleted_events.send(StaminaDepletedEvent::new(entity));
+        } else if stamina.exhausted && stamina.current >= stamina.recovery_threshold {
+            stamina.exhausted = false;
+            recovered_events.send(StaminaRecoveredEvent::new(entity));
+        }
+    }
+}
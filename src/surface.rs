@@ -0,0 +1,11 @@
+// Surface properties of the ground, read through `Ground`. They need `Ground::hit` to name the
+// ground entity, so with PhysX the colliders also need `GroundBounds`
+use bevy::prelude::*;
+
+/// Insert on a collider to move characters standing on it, e.g. a conveyor belt. The velocity is
+/// in world space, in m/s.
+pub struct SurfaceVelocity(pub Vec3);
+
+/// Insert on a collider to scale how quickly characters standing on it speed up and slow down.
+/// 1.0 is the default, smaller values slide like ice and values between are sluggish like mud.
+pub struct SurfaceFriction(pub f32);
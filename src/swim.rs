@@ -0,0 +1,195 @@
+// Swimming in water volumes
+use crate::{
+    controller::{CharacterController, MovementMode},
+    ledge::{LedgeGrab, LedgeState},
+    look::{LookDirection, LookEntity},
+    stamina::Stamina,
+    volume::contains_point,
+};
+use bevy::prelude::*;
+
+/// Insert on an entity with a `GlobalTransform` to make a box of water. `half_extents` are in the
+/// entity's space and `surface` is the height of the water surface above the entity's origin.
+pub struct WaterVolume {
+    pub half_extents: Vec3,
+    pub surface: f32,
+}
+
+impl WaterVolume {
+    /// A box of water filled to the top
+    pub fn new(half_extents: Vec3) -> Self {
+        Self {
+            half_extents,
+            surface: half_extents.y,
+        }
+    }
+
+    pub fn contains(&self, volume_transform: &GlobalTransform, point: Vec3) -> bool {
+        contains_point(self.half_extents, volume_transform, point)
+    }
+
+    /// World space height of the water surface
+    pub fn surface_height(&self, volume_transform: &GlobalTransform) -> f32 {
+        volume_transform.translation.y + self.surface
+    }
+}
+
+/// Insert on the character body. Once the body is submerged past `swim_threshold` the character
+/// swims: movement follows `LookDirection::forward` like flying, jump swims up, crouch swims down,
+/// buoyancy floats the character at the surface and drag slows it down. Jumping forward at the
+/// surface climbs out, and is caught by `LedgeGrab` when the edge is too high.
+pub struct Swimmer {
+    /// Height of the character from the feet to the top of the head
+    pub height: f32,
+    /// Height of the body origin above the feet
+    pub feet_offset: f32,
+    /// Fraction of `height` under water at which swimming starts
+    pub swim_threshold: f32,
+    /// Fraction of `height` under water at which the character floats
+    pub float_submersion: f32,
+    /// Swimming speed in m/s
+    pub speed: f32,
+    /// Rate at which the velocity approaches the desired swimming velocity, per second
+    pub drag: f32,
+    /// Upward speed in m/s when climbing out at the surface
+    pub climb_out_speed: f32,
+    /// Current fraction of `height` under water
+    pub submersion: f32,
+    pub swimming: bool,
+    /// The water volume the character is in
+    pub water: Option<Entity>,
+    climb_out_remaining: f32,
+}
+
+impl Default for Swimmer {
+    fn default() -> Self {
+        Self {
+            height: 1.8,
+            feet_offset: 1.0,
+            swim_threshold: 0.6,
+            float_submersion: 0.8,
+            speed: 3.0,
+            drag: 3.0,
+            climb_out_speed: 5.0,
+            submersion: 0.0,
+            swimming: false,
+            water: None,
+            climb_out_remaining: 0.0,
+        }
+    }
+}
+
+pub fn swim(
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut query: Query<(
+        &LookEntity,
+        &GlobalTransform,
+        &mut CharacterController,
+        &mut Swimmer,
+        Option<&LedgeGrab>,
+        Option<&Stamina>,
+    )>,
+    water_query: Query<(Entity, &WaterVolume, &GlobalTransform)>,
+    look_direction_query: Query<&LookDirection>,
+) {
+    let xz = Vec3::new(1.0, 0.0, 1.0);
+    for (look_entity, transform, mut controller, mut swimmer, ledge, stamina) in query.iter_mut() {
+        swimmer.climb_out_remaining -= time.delta_seconds();
+        let feet = transform.translation - Vec3::unit_y() * swimmer.feet_offset;
+        let water = water_query
+            .iter()
+            .find(|(_, water, water_transform)| water.contains(water_transform, feet));
+        swimmer.water = water.map(|(entity, _, _)| entity);
+        swimmer.submersion = water.map_or(0.0, |(_, water, water_transform)| {
+            let depth = water.surface_height(water_transform) - feet.y;
+            (depth / swimmer.height.max(1E-3)).max(0.0).min(1.0)
+        });
+
+        let hanging = ledge.map_or(false, |ledge| ledge.state != LedgeState::None);
+        if hanging || swimmer.climb_out_remaining > 0.0 {
+            swimmer.swimming = false;
+            continue;
+        }
+        // Leave a margin below the threshold so that bobbing at the surface does not toggle
+        if swimmer.submersion >= swimmer.swim_threshold {
+            swimmer.swimming = true;
+        } else if swimmer.submersion < swimmer.swim_threshold - 0.1 {
+            if swimmer.swimming {
+                controller.jumping = true;
+            }
+            swimmer.swimming = false;
+        }
+        if !swimmer.swimming {
+            continue;
+        }
+        // Not grounded, so that gravity applies after leaving the water
+        controller.jumping = true;
+
+        let look = look_direction_query
+            .get_component::<LookDirection>(look_entity.0)
+            .expect("Failed to get LookDirection from Entity");
+        let input_map = &controller.input_map;
+        let forward = keyboard_input.pressed(input_map.key_forward);
+        let backward = keyboard_input.pressed(input_map.key_backward);
+        let left = keyboard_input.pressed(input_map.key_left);
+        let right = keyboard_input.pressed(input_map.key_right);
+        let up = keyboard_input.pressed(input_map.key_jump);
+        let down = keyboard_input.pressed(input_map.key_crouch);
+        let at_surface = swimmer.submersion <= swimmer.float_submersion + 0.05;
+
+        if at_surface
+            && forward
+            && keyboard_input.just_pressed(input_map.key_jump)
+            && stamina.map_or(true, |stamina| stamina.can_jump())
+        {
+            // Climb out over the edge
+            swimmer.swimming = false;
+            controller.jumped = true;
+            swimmer.climb_out_remaining = 0.5;
+            let forward = (look.forward * xz).normalize();
+            controller.set_velocity_once(
+                MovementMode::Swim,
+                forward * swimmer.speed + Vec3::unit_y() * swimmer.climb_out_speed,
+            );
+            continue;
+        }
+
+        let mut desired_velocity = Vec3::zero();
+        if forward {
+            desired_velocity += look.forward;
+        }
+        if backward {
+            desired_velocity -= look.forward;
+        }
+        if right {
+            desired_velocity += look.right;
+        }
+        if left {
+            desired_velocity -= look.right;
+        }
+        if up {
+            desired_velocity += Vec3::unit_y();
+        }
+        if down {
+            desired_velocity -= Vec3::unit_y();
+        }
+        if desired_velocity.length_squared() > 1E-6 {
+            desired_velocity = desired_velocity.normalize() * swimmer.speed;
+        }
+        if at_surface && !up {
+            // Swim along the surface rather than out of the water
+            desired_velocity.y = desired_velocity.y.min(0.0);
+        }
+
+        let dt = controller.dt;
+        let mut velocity = controller.velocity;
+        velocity += (desired_velocity - velocity) * (swimmer.drag * dt).min(1.0);
+        // Buoyancy balances gravity at the float depth. The override is the whole velocity, so
+        // gravity is only integrated here
+        let gravity = controller.gravity;
+        let buoyancy = gravity * swimmer.submersion / swimmer.float_submersion.max(1E-3);
+        velocity.y += (buoyancy - gravity) * dt;
+        controller.override_velocity(MovementMode::Swim, velocity);
+    }
+}
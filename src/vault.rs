@@ -0,0 +1,339 @@
+// Automatic vaulting over low obstacles
+use crate::{
+    controller::{CharacterController, MovementMode},
+    look::{LookDirection, LookEntity},
+    modifiers::SpeedModifiers,
+    probe::RayHit,
+};
+use bevy::prelude::*;
+
+/// An obstacle in front of the character, measured by the physics backends
+#[derive(Clone, Copy, Debug)]
+pub struct VaultObstacle {
+    /// Point on the front face of the obstacle
+    pub front: Vec3,
+    /// World space height of the top of the obstacle
+    pub top: f32,
+    /// Height of the obstacle above the character's feet
+    pub height: f32,
+    /// Depth of the obstacle along the forward direction
+    pub depth: f32,
+}
+
+/// Insert on the character body. When running towards an obstacle between knee and chest height
+/// that is shallow enough, the character vaults over it along a scripted arc. Collisions of the
+/// body are disabled by the Rapier backend while `vaulting` is set. The PhysX backends do not
+/// support disabling them, so there `clearance` has to lift the collider over the obstacle.
+pub struct Vault {
+    /// Minimum obstacle height above the feet in metres
+    pub knee_height: f32,
+    /// Maximum obstacle height above the feet in metres
+    pub chest_height: f32,
+    /// Maximum obstacle depth in metres
+    pub max_depth: f32,
+    /// Distance ahead in metres to look for obstacles
+    pub detect_distance: f32,
+    /// Minimum horizontal speed in m/s to vault
+    pub min_speed: f32,
+    /// Height of the body origin above the feet
+    pub feet_offset: f32,
+    /// Clearance in metres above the obstacle at the top of the arc
+    pub clearance: f32,
+    /// Horizontal distance in metres beyond the far face of the obstacle to land at
+    pub landing_clearance: f32,
+    /// Radius in metres of the probes that measure obstacles, so that narrow gaps and thin rails
+    /// are not missed
+    pub probe_radius: f32,
+    /// Duration of the vault in seconds
+    pub duration: f32,
+    /// Obstacle ahead, written by the physics backends
+    pub obstacle: Option<VaultObstacle>,
+    /// Horizontal direction to look for obstacles in, for the physics backends
+    pub forward: Vec3,
+    /// Whether a vault is in progress, e.g. for animation
+    pub vaulting: bool,
+    /// Progress through the vault in [0, 1], e.g. for animation
+    pub progress: f32,
+    from: Vec3,
+    to: Vec3,
+    peak: f32,
+}
+
+impl Default for Vault {
+    fn default() -> Self {
+        Self {
+            knee_height: 0.4,
+            chest_height: 1.3,
+            max_depth: 1.0,
+            detect_distance: 1.2,
+            min_speed: 4.0,
+            feet_offset: 1.2,
+            clearance: 0.2,
+            landing_clearance: 0.6,
+            probe_radius: 0.1,
+            duration: 0.5,
+            obstacle: None,
+            forward: -Vec3::unit_z(),
+            vaulting: false,
+            progress: 0.0,
+            from: Vec3::zero(),
+            to: Vec3::zero(),
+            peak: 0.0,
+        }
+    }
+}
+
+impl Vault {
+    fn can_vault(&self, obstacle: &VaultObstacle) -> bool {
+        obstacle.height >= self.knee_height
+            && obstacle.height <= self.chest_height
+            && obstacle.depth <= self.max_depth
+    }
+
+    fn position(&self, t: f32) -> Vec3 {
+        let t = t.max(0.0).min(1.0);
+        let base = self.from.lerp(self.to, t);
+        let arc = (self.peak - base.y).max(0.0) * (std::f32::consts::PI * t).sin();
+        base + Vec3::unit_y() * arc
+    }
+}
+
+pub fn vault(
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut query: Query<(
+        &LookEntity,
+        &GlobalTransform,
+        &mut CharacterController,
+        &mut Vault,
+        Option<&SpeedModifiers>,
+    )>,
+    look_direction_query: Query<&LookDirection>,
+) {
+    let xz = Vec3::new(1.0, 0.0, 1.0);
+    for (look_entity, transform, mut controller, mut vault, modifiers) in query.iter_mut() {
+        let position = transform.translation;
+
+        if vault.vaulting {
+            vault.progress += time.delta_seconds() / vault.duration.max(1E-3);
+            if vault.progress >= 1.0 {
+                vault.vaulting = false;
+                vault.progress = 0.0;
+                continue;
+            }
+            // Move exactly to the next point on the arc
+            let next = vault.position(vault.progress + controller.dt / vault.duration.max(1E-3));
+            let velocity = (next - position) / controller.dt;
+            controller.override_velocity(MovementMode::Vault, velocity);
+            continue;
+        }
+
+        let look = look_direction_query
+            .get_component::<LookDirection>(look_entity.0)
+            .expect("Failed to get LookDirection from Entity");
+        vault.forward = (look.forward * xz).normalize();
+
+        let running = keyboard_input.pressed(controller.input_map.key_run)
+            && keyboard_input.pressed(controller.input_map.key_forward);
+        let speed = (controller.velocity * xz).length();
+        let rooted = modifiers.map_or(false, |modifiers| modifiers.rooted());
+        if controller.jumping || !running || rooted || speed < vault.min_speed {
+            continue;
+        }
+        if let Some(obstacle) = vault.obstacle.filter(|obstacle| vault.can_vault(obstacle)) {
+            let forward = vault.forward;
+            let distance = (obstacle.front - position) * xz;
+            vault.vaulting = true;
+            vault.progress = 0.0;
+            vault.from = position;
+            vault.to = position
+                + forward * (forward.dot(distance) + obstacle.depth + vault.landing_clearance);
+            vault.peak = obstacle.top + vault.feet_offset + vault.clearance;
+        }
+    }
+}
+
+/// Cast a cylinder of `radius` as rays along its axis and around its rim, returning the nearest hit
+fn cast_cylinder(
+    cast: &impl Fn(Vec3, Vec3, f32) -> Option<RayHit>,
+    origin: Vec3,
+    direction: Vec3,
+    max_distance: f32,
+    radius: f32,
+) -> Option<RayHit> {
+    let side = if direction.y.abs() > 0.9 {
+        Vec3::unit_x()
+    } else {
+        Vec3::unit_y()
+    };
+    let u = direction.cross(side).normalize();
+    let v = direction.cross(u);
+    let rim = (0..8).map(|i| {
+        let angle = i as f32 * std::f32::consts::FRAC_PI_4;
+        (u * angle.cos() + v * angle.sin()) * radius
+    });
+    std::iter::once(Vec3::zero())
+        .chain(rim)
+        .filter_map(|offset| cast(origin + offset, direction, max_distance))
+        .min_by(|a, b| {
+            a.distance
+                .partial_cmp(&b.distance)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })
+}
+
+/// Measure the obstacle ahead with `cast(origin, direction, max_distance)`, used by the physics
+/// backends to fill in `Vault::obstacle`. Each probe sweeps `Vault::probe_radius` with several
+/// rays, so it works with any backend's ray casts and their exclusion of the character's own body.
+pub fn measure_obstacle(
+    vault: &Vault,
+    position: Vec3,
+    cast: impl Fn(Vec3, Vec3, f32) -> Option<RayHit>,
+) -> Option<VaultObstacle> {
+    let forward = vault.forward;
+    let feet = position.y - vault.feet_offset;
+    let radius = vault.probe_radius;
+
+    // Front face at knee height
+    let knee = Vec3::new(position.x, feet + vault.knee_height, position.z);
+    let front = cast_cylinder(&cast, knee, forward, vault.detect_distance, radius)?;
+    if front.normal.y.abs() > 0.3 {
+        // A slope rather than an obstacle
+        return None;
+    }
+
+    // Top, searching down from just above chest height
+    let mut above = front.point + forward * 0.05;
+    above.y = feet + vault.chest_height + 0.1;
+    let top = cast_cylinder(&cast, above, -Vec3::unit_y(), above.y - knee.y, radius)?;
+    if top.distance < 1E-3 {
+        // Taller than chest height
+        return None;
+    }
+
+    // Far face, casting back towards the character from beyond the maximum depth
+    let reach = vault.max_depth + 0.1;
+    let mut beyond = front.point + forward * reach;
+    beyond.y = top.point.y - 0.05;
+    let back = cast_cylinder(&cast, beyond, -forward, reach, radius)?;
+
+    Some(VaultObstacle {
+        front: front.point,
+        top: top.point.y,
+        height: top.point.y - feet,
+        depth: reach - back.distance,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Ray cast against axis aligned boxes given by their min and max corners
+    fn cast_boxes(
+        boxes: &[(Vec3, Vec3)],
+        origin: Vec3,
+        direction: Vec3,
+        max: f32,
+    ) -> Option<RayHit> {
+        let mut closest: Option<RayHit> = None;
+        for (min, max_corner) in boxes.iter() {
+            let mut t_near = 0.0f32;
+            let mut t_far = max;
+            let mut normal = Vec3::zero();
+            let mut hit = true;
+            for axis in [Vec3::unit_x(), Vec3::unit_y(), Vec3::unit_z()].iter() {
+                let (o, d) = (origin.dot(*axis), direction.dot(*axis));
+                let (lo, hi) = (min.dot(*axis), max_corner.dot(*axis));
+                if d.abs() < 1E-6 {
+                    if o < lo || o > hi {
+                        hit = false;
+                    }
+                    continue;
+                }
+                let (t0, t1) = ((lo - o) / d, (hi - o) / d);
+                let (t0, t1) = (t0.min(t1), t0.max(t1));
+                if t0 > t_near {
+                    t_near = t0;
+                    normal = *axis * -d.signum();
+                }
+                t_far = t_far.min(t1);
+            }
+            if hit && t_near <= t_far && closest.map_or(true, |hit| t_near < hit.distance) {
+                closest = Some(RayHit {
+                    entity: None,
+                    point: origin + direction * t_near,
+                    normal,
+                    distance: t_near,
+                });
+            }
+        }
+        closest
+    }
+
+    fn vault() -> Vault {
+        Vault {
+            forward: Vec3::unit_z(),
+            ..Default::default()
+        }
+    }
+
+    // The character's feet are at the origin
+    fn position(vault: &Vault) -> Vec3 {
+        Vec3::unit_y() * vault.feet_offset
+    }
+
+    #[test]
+    fn measures_low_wall() {
+        let vault = vault();
+        let wall = [(Vec3::new(-2.0, 0.0, 0.8), Vec3::new(2.0, 0.8, 1.2))];
+        let obstacle = measure_obstacle(&vault, position(&vault), |o, d, m| {
+            cast_boxes(&wall, o, d, m)
+        })
+        .expect("Failed to measure the wall");
+        assert!((obstacle.front.z - 0.8).abs() < 1E-3);
+        assert!((obstacle.top - 0.8).abs() < 1E-3);
+        assert!((obstacle.height - 0.8).abs() < 1E-3);
+        assert!((obstacle.depth - 0.4).abs() < 1E-3);
+        assert!(vault.can_vault(&obstacle));
+    }
+
+    #[test]
+    fn finds_thin_rail_off_centre() {
+        let vault = vault();
+        // Narrower than the probe and beside the centre ray
+        let rail = [(Vec3::new(0.05, 0.0, 0.8), Vec3::new(0.09, 0.8, 0.9))];
+        let obstacle = measure_obstacle(&vault, position(&vault), |o, d, m| {
+            cast_boxes(&rail, o, d, m)
+        });
+        assert!(obstacle.is_some());
+    }
+
+    #[test]
+    fn rejects_tall_wall() {
+        let vault = vault();
+        let wall = [(Vec3::new(-2.0, 0.0, 0.8), Vec3::new(2.0, 3.0, 1.2))];
+        let obstacle = measure_obstacle(&vault, position(&vault), |o, d, m| {
+            cast_boxes(&wall, o, d, m)
+        });
+        assert!(obstacle.is_none());
+    }
+
+    #[test]
+    fn deep_obstacles_cannot_be_vaulted() {
+        let vault = vault();
+        let block = [(Vec3::new(-2.0, 0.0, 0.8), Vec3::new(2.0, 0.8, 4.0))];
+        let obstacle = measure_obstacle(&vault, position(&vault), |o, d, m| {
+            cast_boxes(&block, o, d, m)
+        });
+        assert!(obstacle.map_or(true, |obstacle| !vault.can_vault(&obstacle)));
+    }
+
+    #[test]
+    fn nothing_ahead() {
+        let vault = vault();
+        let obstacle =
+            measure_obstacle(&vault, position(&vault), |o, d, m| cast_boxes(&[], o, d, m));
+        assert!(obstacle.is_none());
+    }
+}
@@ -0,0 +1,41 @@
+// Box volumes shared by triggers, water, pads and fields
+use bevy::prelude::*;
+
+/// Whether `point` is inside the box of `half_extents` placed and oriented by `transform`. Scale is
+/// ignored, so `half_extents` are in world units.
+pub fn contains_point(half_extents: Vec3, transform: &GlobalTransform, point: Vec3) -> bool {
+    let local = transform.rotation.conjugate() * (point - transform.translation);
+    local.x.abs() <= half_extents.x
+        && local.y.abs() <= half_extents.y
+        && local.z.abs() <= half_extents.z
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contains_point_uses_rotation() {
+        let transform = GlobalTransform {
+            translation: Vec3::new(0.0, 1.0, 0.0),
+            rotation: Quat::from_rotation_y(std::f32::consts::FRAC_PI_2),
+            scale: Vec3::one(),
+        };
+        let half_extents = Vec3::new(2.0, 0.5, 0.5);
+        assert!(contains_point(
+            half_extents,
+            &transform,
+            Vec3::new(0.0, 1.0, 1.5)
+        ));
+        assert!(!contains_point(
+            half_extents,
+            &transform,
+            Vec3::new(1.5, 1.0, 0.0)
+        ));
+        assert!(!contains_point(
+            half_extents,
+            &transform,
+            Vec3::new(0.0, 2.0, 0.0)
+        ));
+    }
+}
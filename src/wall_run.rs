@@ -0,0 +1,190 @@
+// Wall running and wall jumping
+use crate::{
+    camera::CameraOffset,
+    controller::{CharacterController, MovementMode},
+    look::{LookDirection, LookEntity},
+    modifiers::SpeedModifiers,
+    probe::RayHit,
+    stamina::Stamina,
+};
+use bevy::prelude::*;
+
+/// Insert on the character body. While airborne, moving forward and fast enough next to a wall
+/// reported by the physics backends, the character runs along the wall without gravity for a
+/// limited time. Jumping pushes off along the wall normal.
+pub struct WallRun {
+    /// Length of the sideways rays from the body origin used to find walls
+    pub detect_distance: f32,
+    /// Minimum horizontal speed in m/s to start a wall run
+    pub min_speed: f32,
+    /// Maximum wall run duration in seconds, reset on landing
+    pub max_duration: f32,
+    /// Speed in m/s away from the wall when wall jumping
+    pub jump_off_speed: f32,
+    /// Upward speed in m/s when wall jumping
+    pub jump_up_speed: f32,
+    /// Camera roll away from the wall in radians
+    pub camera_roll: f32,
+    /// Rate at which the camera roll follows its target, per second
+    pub roll_speed: f32,
+    /// Wall to the left of the character, written by the physics backends
+    pub wall_left: Option<RayHit>,
+    /// Wall to the right of the character, written by the physics backends
+    pub wall_right: Option<RayHit>,
+    /// Horizontal right direction of the character, for the physics backends
+    pub right: Vec3,
+    pub running: bool,
+    pub wall_normal: Vec3,
+    /// Side of the wall being run on, 1 for right and -1 for left
+    pub side: f32,
+    elapsed: f32,
+    roll: f32,
+}
+
+impl Default for WallRun {
+    fn default() -> Self {
+        Self {
+            detect_distance: 0.8,
+            min_speed: 4.0,
+            max_duration: 1.5,
+            jump_off_speed: 6.0,
+            jump_up_speed: 6.0,
+            camera_roll: 0.2,
+            roll_speed: 8.0,
+            wall_left: None,
+            wall_right: None,
+            right: Vec3::unit_x(),
+            running: false,
+            wall_normal: Vec3::zero(),
+            side: 0.0,
+            elapsed: 0.0,
+            roll: 0.0,
+        }
+    }
+}
+
+impl WallRun {
+    /// The closest runnable wall and its side
+    fn wall(&self) -> Option<(RayHit, f32)> {
+        // Only near vertical surfaces count as walls
+        let is_wall = |hit: &RayHit| hit.normal.y.abs() < 0.3;
+        let left = self.wall_left.filter(is_wall).map(|hit| (hit, -1.0));
+        let right = self.wall_right.filter(is_wall).map(|hit| (hit, 1.0));
+        match (left, right) {
+            (Some(left), Some(right)) => Some(if left.0.distance < right.0.distance {
+                left
+            } else {
+                right
+            }),
+            (left, right) => left.or(right),
+        }
+    }
+}
+
+pub fn wall_run(
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut query: Query<(
+        &LookEntity,
+        &mut CharacterController,
+        &mut WallRun,
+        Option<&Stamina>,
+        Option<&SpeedModifiers>,
+    )>,
+    look_direction_query: Query<&LookDirection>,
+) {
+    let xz = Vec3::new(1.0, 0.0, 1.0);
+    for (look_entity, mut controller, mut wall_run, stamina, modifiers) in query.iter_mut() {
+        let look = look_direction_query
+            .get_component::<LookDirection>(look_entity.0)
+            .expect("Failed to get LookDirection from Entity");
+        let forward = (look.forward * xz).normalize();
+        wall_run.right = (look.right * xz).normalize();
+
+        if !controller.jumping {
+            wall_run.running = false;
+            wall_run.elapsed = 0.0;
+            continue;
+        }
+
+        let horizontal_velocity = controller.velocity * xz;
+        let wall = wall_run.wall();
+        let can_run = wall.is_some()
+            && wall_run.elapsed < wall_run.max_duration
+            && keyboard_input.pressed(controller.input_map.key_forward);
+        let rooted = modifiers.map_or(false, |modifiers| modifiers.rooted());
+        if !wall_run.running
+            && can_run
+            && !rooted
+            && horizontal_velocity.length() >= wall_run.min_speed
+        {
+            wall_run.running = true;
+        }
+        if !can_run {
+            wall_run.running = false;
+        }
+        if !wall_run.running {
+            continue;
+        }
+
+        let (hit, side) = wall.expect("Wall run without a wall");
+        let normal = (hit.normal * xz).normalize();
+        wall_run.wall_normal = normal;
+        wall_run.side = side;
+        wall_run.elapsed += time.delta_seconds();
+
+        // Move along the wall tangent in the direction the character is facing, or moving when
+        // facing straight at the wall
+        let tangent = [forward, horizontal_velocity]
+            .iter()
+            .map(|direction| *direction - normal * direction.dot(normal))
+            .find(|tangent| tangent.length_squared() > 1E-6);
+        let tangent = match tangent {
+            Some(tangent) => tangent.normalize(),
+            None => {
+                wall_run.running = false;
+                continue;
+            }
+        };
+        let speed = horizontal_velocity.length().max(controller.walk_speed);
+
+        if keyboard_input.just_pressed(controller.input_map.key_jump)
+            && stamina.map_or(true, |stamina| stamina.can_jump())
+        {
+            wall_run.running = false;
+            controller.jumped = true;
+            controller.set_velocity_once(
+                MovementMode::WallRun,
+                tangent * speed
+                    + normal * wall_run.jump_off_speed
+                    + Vec3::unit_y() * wall_run.jump_up_speed,
+            );
+        } else {
+            // Zero vertical velocity cancels gravity and a small push keeps contact with the wall
+            controller.override_velocity(MovementMode::WallRun, tangent * speed - normal * 0.5);
+        }
+    }
+}
+
+pub fn wall_run_camera(
+    time: Res<Time>,
+    mut query: Query<(&LookEntity, &mut WallRun)>,
+    mut camera_query: Query<&mut CameraOffset>,
+) {
+    for (look_entity, mut wall_run) in query.iter_mut() {
+        // Roll away from the wall
+        let target = if wall_run.running {
+            wall_run.side * wall_run.camera_roll
+        } else {
+            0.0
+        };
+        let step = (wall_run.roll_speed * time.delta_seconds()).min(1.0);
+        wall_run.roll += (target - wall_run.roll) * step;
+        if let Ok(mut offset) = camera_query.get_mut(look_entity.0) {
+            offset.add(
+                Vec3::zero(),
+                Quat::from_rotation_ypr(0.0, 0.0, wall_run.roll),
+            );
+        }
+    }
+}